@@ -0,0 +1,411 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// UI language selected with `?lang=`.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Ja,
+    Zh,
+}
+
+impl Language {
+    /// Looks up a UI string, falling back to English for keys missing from a bundle and
+    /// to the key itself if even English lacks it.
+    pub fn translate(self, key: &str) -> &str {
+        let bundle = match self {
+            Language::En => &EN,
+            Language::Ja => &JA,
+            Language::Zh => &ZH,
+        };
+
+        bundle
+            .get(key)
+            .or_else(|| EN.get(key))
+            .copied()
+            .unwrap_or(key)
+    }
+}
+
+type Bundle = LazyLock<HashMap<&'static str, &'static str>>;
+
+static EN: Bundle = LazyLock::new(|| {
+    HashMap::from([
+        ("nickname", "Nickname"),
+        ("share", "Share"),
+        ("not_found_in_rule", "No player found in"),
+        ("error_player_not_found", "No player named {} was found"),
+        (
+            "error_player_id_not_found",
+            "No games were found for player ID {}",
+        ),
+        ("error_title_bad_request", "Invalid request"),
+        ("error_title_not_found", "Not found"),
+        ("error_title_internal", "Server error"),
+        ("error_title_upstream", "Stats server unavailable"),
+        (
+            "error_upstream",
+            "Couldn't reach the stats server, try again",
+        ),
+        ("error_bad_request", "The request had an invalid parameter"),
+        ("found_in_other_rule", "This name has games in"),
+        ("last_game", "Last game"),
+        ("current_rank", "Rank"),
+        ("peak_rank", "peak"),
+        ("minutes_ago", "{} minutes ago"),
+        ("hours_ago", "{} hours ago"),
+        ("days_ago", "{} days ago"),
+        ("name_collision", "Several players use this name"),
+        ("season", "Season"),
+        (
+            "scored_with_placement_points",
+            "Points below use placement points",
+        ),
+        ("today_since", "Today since"),
+        ("history_depth", "History"),
+        ("all_time", "All time"),
+        ("last_30_days", "Last 30 days"),
+        ("last_90_days", "Last 90 days"),
+        ("this_season", "This season"),
+        ("as_of", "Stats as of"),
+        ("top_half_streak", "Current top-half streak"),
+        ("last_place_streak", "Current last-place streak"),
+        ("top_half_streaks", "Top-half streaks"),
+        ("last_place_streaks", "Last-place streaks"),
+        ("recent_placements", "Recent placements"),
+        (
+            "placement_games_south",
+            "Placement stats below (recent placements, monthly trend, placement rates, consistency and rating) cover South games only; points cover all games",
+        ),
+        ("monthly_placement", "Average placement by month"),
+        ("placements", "Placements"),
+        ("neutral_rate", "Neutral rate"),
+        ("average_final_score", "Avg. final score"),
+        ("best_room", "Best room"),
+        ("worst_room", "worst room"),
+        ("most_played", "Most played"),
+        ("games", "games"),
+        ("game_count", "Games"),
+        ("longest_game", "Longest game"),
+        ("shortest_game", "Shortest game"),
+        ("minutes", "min"),
+        ("longest_break", "Longest break"),
+        ("days", "days"),
+        ("pt_per_hour", "Points per hour"),
+        ("time_played", "Total time played"),
+        ("per_game", "per game"),
+        ("net_pt", "Net points"),
+        ("gross_gained", "gained"),
+        ("gross_lost", "lost"),
+        ("consistency", "Consistency"),
+        (
+            "consistency_note",
+            "Average placement change between consecutive games; lower is steadier",
+        ),
+        ("rating_estimate", "Rating (unofficial estimate)"),
+        (
+            "rating_estimate_note",
+            "Tenhou-style R replayed from placements, assuming every table averages R1500",
+        ),
+        ("average_pt_change_per_game", "Average points change"),
+        ("when_gaining", "when gaining"),
+        ("when_losing", "when losing"),
+        ("score_above_zero", "Final score above zero"),
+        ("score_below_zero", "below zero"),
+        ("score_near_zero", "within ±1000 of zero"),
+        ("average_opponent_level", "Average opponent level"),
+        ("average_rank", "avg. rank"),
+        ("time_of_day_placement", "Average placement between"),
+        ("versus_overall", "vs. overall"),
+        ("average_placement", "Average placement"),
+        ("first_place_rate", "1st place rate (%)"),
+        ("placement_spread", "Placement spread"),
+        (
+            "placement_spread_note",
+            "Standard deviation of placements; lower is more consistent",
+        ),
+        ("total_pt_change", "Total points"),
+        ("average_duration", "avg. duration"),
+        ("no_games", "No games in this range"),
+        ("delta", "Change"),
+        ("low_sample", "low sample"),
+        ("length_weighted", "Length-weighted"),
+        ("south_games_count", "South games count"),
+        ("average_pt_change", "avg. points"),
+        (
+            "skipped_records",
+            "records could not be parsed and are not included",
+        ),
+        (
+            "incomplete_games",
+            "games without a valid end time are not included",
+        ),
+        (
+            "partial_history",
+            "Partial results: the time budget ran out before the full history was fetched, so older games are missing.",
+        ),
+        (
+            "truncated_history",
+            "Games shown in the table (the statistics above cover all)",
+        ),
+        ("rank", "Rank"),
+        ("placement_from_record", "Placement as recorded by the game"),
+        (
+            "placement_from_points",
+            "Placement recomputed from points change",
+        ),
+        ("date_time", "Date & Time"),
+        ("duration", "Duration"),
+        ("game_type", "Game Type"),
+        ("pt_change", "Points Change"),
+        ("cumulative_pt", "Cumulative Points"),
+        ("margin", "Margin"),
+        ("opponent_level", "Opponent Level"),
+        ("players_scores", "Players & Scores"),
+    ])
+});
+
+static JA: Bundle = LazyLock::new(|| {
+    HashMap::from([
+        ("nickname", "ニックネーム"),
+        ("share", "共有"),
+        ("not_found_in_rule", "プレイヤーが見つかりません："),
+        (
+            "error_player_not_found",
+            "「{}」という名前のプレイヤーは見つかりません",
+        ),
+        (
+            "error_player_id_not_found",
+            "プレイヤーID {} の対局は見つかりません",
+        ),
+        ("error_title_bad_request", "不正なリクエスト"),
+        ("error_title_not_found", "見つかりません"),
+        ("error_title_internal", "サーバーエラー"),
+        ("error_title_upstream", "統計サーバーに接続できません"),
+        (
+            "error_upstream",
+            "統計サーバーに接続できませんでした。もう一度お試しください",
+        ),
+        ("error_bad_request", "リクエストのパラメータが不正です"),
+        ("found_in_other_rule", "この名前の対局があります："),
+        ("last_game", "最終対局"),
+        ("current_rank", "段位"),
+        ("peak_rank", "最高"),
+        ("minutes_ago", "{}分前"),
+        ("hours_ago", "{}時間前"),
+        ("days_ago", "{}日前"),
+        ("name_collision", "この名前のプレイヤーが複数います"),
+        ("season", "シーズン"),
+        (
+            "scored_with_placement_points",
+            "以下のポイントは順位点で計算",
+        ),
+        ("today_since", "本日"),
+        ("history_depth", "期間"),
+        ("all_time", "全期間"),
+        ("last_30_days", "過去30日"),
+        ("last_90_days", "過去90日"),
+        ("this_season", "今シーズン"),
+        ("as_of", "集計基準日"),
+        ("top_half_streak", "連続上位"),
+        ("last_place_streak", "連続ラス"),
+        ("top_half_streaks", "連続上位の回数"),
+        ("last_place_streaks", "連続ラスの回数"),
+        ("recent_placements", "最近の着順"),
+        (
+            "placement_games_south",
+            "以下の順位系の統計（最近の着順・月別推移・着順分布・安定度・レート）は半荘戦のみ、ポイントは全対局が対象",
+        ),
+        ("monthly_placement", "月別平均順位"),
+        ("placements", "着順分布"),
+        ("neutral_rate", "均等時の割合"),
+        ("average_final_score", "平均終局持ち点"),
+        ("best_room", "得意な部屋"),
+        ("worst_room", "苦手な部屋"),
+        ("most_played", "最多プレイ"),
+        ("games", "戦"),
+        ("game_count", "対局数"),
+        ("longest_game", "最長対局"),
+        ("shortest_game", "最短対局"),
+        ("minutes", "分"),
+        ("longest_break", "最長の休止"),
+        ("days", "日"),
+        ("pt_per_hour", "1時間あたりのポイント"),
+        ("time_played", "総プレイ時間"),
+        ("per_game", "1局あたり"),
+        ("net_pt", "純ポイント"),
+        ("gross_gained", "獲得"),
+        ("gross_lost", "喪失"),
+        ("consistency", "安定度"),
+        (
+            "consistency_note",
+            "連続する対局間の順位変動の平均。低いほど安定",
+        ),
+        ("rating_estimate", "レート（非公式の推定値）"),
+        (
+            "rating_estimate_note",
+            "着順から天鳳方式で算出。卓の平均レートはR1500と仮定",
+        ),
+        ("average_pt_change_per_game", "平均ポイント増減"),
+        ("when_gaining", "プラス時"),
+        ("when_losing", "マイナス時"),
+        ("score_above_zero", "終局持ち点プラス"),
+        ("score_below_zero", "マイナス"),
+        ("score_near_zero", "±1000以内"),
+        ("average_opponent_level", "対戦相手の平均段位"),
+        ("average_rank", "平均順位"),
+        ("time_of_day_placement", "時間帯別の平均順位"),
+        ("versus_overall", "／全体"),
+        ("average_placement", "平均順位"),
+        ("first_place_rate", "トップ率 (%)"),
+        ("placement_spread", "順位のばらつき"),
+        ("placement_spread_note", "順位の標準偏差。低いほど安定"),
+        ("total_pt_change", "合計ポイント"),
+        ("average_duration", "平均対局時間"),
+        ("no_games", "この範囲の対局はありません"),
+        ("delta", "差分"),
+        ("low_sample", "サンプル少"),
+        ("length_weighted", "対局長で加重"),
+        ("south_games_count", "半荘戦の重み"),
+        ("average_pt_change", "平均ポイント"),
+        (
+            "skipped_records",
+            "件の記録を解析できず、集計から除外しました",
+        ),
+        ("incomplete_games", "件の終了時刻が不正な対局を除外しました"),
+        (
+            "partial_history",
+            "一部のみ：時間制限のため、古い対局は取得されていません。",
+        ),
+        (
+            "truncated_history",
+            "表に表示中の対局（統計は全対局が対象）",
+        ),
+        ("rank", "順位"),
+        ("placement_from_record", "牌譜に記録された順位"),
+        ("placement_from_points", "ポイント増減から算出した順位"),
+        ("date_time", "日時"),
+        ("duration", "対局時間"),
+        ("game_type", "部屋"),
+        ("pt_change", "ポイント増減"),
+        ("cumulative_pt", "累計ポイント"),
+        ("margin", "点差"),
+        ("opponent_level", "相手の段位"),
+        ("players_scores", "プレイヤーと持ち点"),
+    ])
+});
+
+static ZH: Bundle = LazyLock::new(|| {
+    HashMap::from([
+        ("nickname", "昵称"),
+        ("share", "分享"),
+        ("not_found_in_rule", "未找到该玩家："),
+        ("error_player_not_found", "未找到名为“{}”的玩家"),
+        ("error_player_id_not_found", "未找到玩家ID {} 的对局"),
+        ("error_title_bad_request", "请求无效"),
+        ("error_title_not_found", "未找到"),
+        ("error_title_internal", "服务器错误"),
+        ("error_title_upstream", "统计服务器不可用"),
+        ("error_upstream", "无法连接统计服务器，请重试"),
+        ("error_bad_request", "请求参数无效"),
+        ("found_in_other_rule", "该名称在此有对局："),
+        ("last_game", "最近对局"),
+        ("current_rank", "段位"),
+        ("peak_rank", "最高"),
+        ("minutes_ago", "{}分钟前"),
+        ("hours_ago", "{}小时前"),
+        ("days_ago", "{}天前"),
+        ("name_collision", "有多名玩家使用此名称"),
+        ("season", "赛季"),
+        ("scored_with_placement_points", "以下得分按顺位分计算"),
+        ("today_since", "今日自"),
+        ("history_depth", "时间范围"),
+        ("all_time", "全部"),
+        ("last_30_days", "最近30天"),
+        ("last_90_days", "最近90天"),
+        ("this_season", "本赛季"),
+        ("as_of", "统计截至"),
+        ("top_half_streak", "当前连续前半"),
+        ("last_place_streak", "当前连续末位"),
+        ("top_half_streaks", "连续前半次数"),
+        ("last_place_streaks", "连续末位次数"),
+        ("recent_placements", "最近顺位"),
+        (
+            "placement_games_south",
+            "以下顺位类统计（最近顺位、每月趋势、顺位分布、稳定度、评分）仅统计半庄，得分统计全部对局",
+        ),
+        ("monthly_placement", "每月平均顺位"),
+        ("placements", "顺位分布"),
+        ("neutral_rate", "均等比例"),
+        ("average_final_score", "平均终局点数"),
+        ("best_room", "最佳场次"),
+        ("worst_room", "最差场次"),
+        ("most_played", "最常玩"),
+        ("games", "局"),
+        ("game_count", "对局数"),
+        ("longest_game", "最长对局"),
+        ("shortest_game", "最短对局"),
+        ("minutes", "分钟"),
+        ("longest_break", "最长间隔"),
+        ("days", "天"),
+        ("pt_per_hour", "每小时得分"),
+        ("time_played", "总游戏时长"),
+        ("per_game", "每局"),
+        ("net_pt", "净得分"),
+        ("gross_gained", "获得"),
+        ("gross_lost", "失去"),
+        ("consistency", "稳定度"),
+        ("consistency_note", "相邻对局顺位变化的平均值，越低越稳定"),
+        ("rating_estimate", "评分（非官方估算）"),
+        (
+            "rating_estimate_note",
+            "按天凤方式由顺位推算，假设每桌平均R1500",
+        ),
+        ("average_pt_change_per_game", "平均得分变化"),
+        ("when_gaining", "得分时"),
+        ("when_losing", "失分时"),
+        ("score_above_zero", "终局点数为正"),
+        ("score_below_zero", "为负"),
+        ("score_near_zero", "±1000以内"),
+        ("average_opponent_level", "对手平均段位"),
+        ("average_rank", "平均顺位"),
+        ("time_of_day_placement", "该时段平均顺位"),
+        ("versus_overall", "/ 总体"),
+        ("average_placement", "平均顺位"),
+        ("first_place_rate", "一位率 (%)"),
+        ("placement_spread", "顺位离散度"),
+        ("placement_spread_note", "顺位的标准差，越低越稳定"),
+        ("total_pt_change", "总得分"),
+        ("average_duration", "平均对局时长"),
+        ("no_games", "此范围内没有对局"),
+        ("delta", "变化"),
+        ("low_sample", "样本不足"),
+        ("length_weighted", "按对局长度加权"),
+        ("south_games_count", "半庄权重"),
+        ("average_pt_change", "平均得分"),
+        ("skipped_records", "条记录无法解析，未计入统计"),
+        ("incomplete_games", "局结束时间无效的对局未计入统计"),
+        (
+            "partial_history",
+            "部分结果：时间预算已用尽，较早的对局缺失。",
+        ),
+        (
+            "truncated_history",
+            "表中显示的对局（以上统计涵盖全部对局）",
+        ),
+        ("rank", "顺位"),
+        ("placement_from_record", "牌谱记录的顺位"),
+        ("placement_from_points", "根据得分变化推算的顺位"),
+        ("date_time", "日期时间"),
+        ("duration", "时长"),
+        ("game_type", "场次"),
+        ("pt_change", "得分变化"),
+        ("cumulative_pt", "累计得分"),
+        ("margin", "分差"),
+        ("opponent_level", "对手段位"),
+        ("players_scores", "玩家与点数"),
+    ])
+});
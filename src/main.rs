@@ -1,314 +1,5782 @@
+mod i18n;
+
 use anyhow::{Result, anyhow};
 use askama::Template;
 use axum::{
-    Router,
-    extract::Path,
-    http::StatusCode,
+    Json, Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Path, Query, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
     response::{Html, IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
 };
-use chrono::{DateTime, Utc};
-use serde_json::Value;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeDelta, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use clap::Parser;
+use futures_util::{StreamExt, stream};
+use i18n::Language;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
 use std::fmt::Display;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use subtle::ConstantTimeEq;
+use tokio::time::{Duration, Instant};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::request_id::{
+    MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::trace::TraceLayer;
+use tracing::{info, info_span, warn};
+use tracing_subscriber::EnvFilter;
 
-#[derive(Template)]
-#[template(path = "user_stats.html")]
-struct UserStatsTemplate {
-    player_name: String,
-    game_history: Vec<GameMatch>,
+/// Listening address unless `--bind` or `MAJSOUL_STATS_BIND` says otherwise.
+const DEFAULT_BIND_ADDRESS: &str = "0.0.0.0:3000";
+
+/// Command-line options; everything else comes from environment variables.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Serves Mahjong Soul player stats from amae-koromo. Apart from the listening \
+             address, configuration is read from environment variables."
+)]
+struct Cli {
+    /// Address and port to listen on, e.g. 127.0.0.1:8080
+    #[arg(long, env = "MAJSOUL_STATS_BIND", default_value = DEFAULT_BIND_ADDRESS)]
+    bind: SocketAddr,
 }
 
-struct HtmlTemplate<T>(T);
+const DEFAULT_USER_AGENT: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/Arcohol/majsoul-stats)"
+);
 
-impl<T> IntoResponse for HtmlTemplate<T>
-where
-    T: Template,
-{
-    fn into_response(self) -> Response {
-        match self.0.render() {
-            Ok(html_content) => Html(html_content).into_response(),
-            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+/// Matches beyond this many are left out of the rendered table.
+const DEFAULT_MAX_RENDERED_MATCHES: usize = 5000;
+
+/// Stats pages slower than this many milliseconds are logged at warn level.
+const DEFAULT_SLOW_REQUEST_MS: u64 = 5000;
+
+/// Nicknames are far shorter than this, so longer names are rejected before searching.
+const MAX_PLAYER_NAME_CHARS: usize = 64;
+
+/// Larger request bodies are refused with 413.
+const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Longer paths plus query strings are refused with 414.
+const DEFAULT_MAX_URI_BYTES: usize = 2048;
+
+/// Requests handled at once; further ones wait for a free slot.
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 64;
+
+/// Idle upstream connections kept open; everything goes to one host, so this is the pool size.
+const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 32;
+
+/// Name searches are answered from memory for this long.
+const DEFAULT_PLAYER_ID_CACHE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Name searches kept at once; the oldest is dropped to make room for a new one.
+const PLAYER_ID_CACHE_CAPACITY: usize = 10_000;
+
+/// Fetched match histories are reused for this long.
+const DEFAULT_HISTORY_CACHE_TTL_SECS: u64 = 10 * 60;
+
+/// Histories kept at once; the oldest is dropped to make room for a new one.
+const HISTORY_CACHE_CAPACITY: usize = 256;
+
+/// Idle upstream connections are closed after this many seconds, matching reqwest's default.
+const DEFAULT_POOL_IDLE_TIMEOUT_SECS: u64 = 90;
+
+#[derive(Clone)]
+struct AppState {
+    http_client: reqwest::Client,
+    max_rendered_matches: usize,
+    /// Used when a request doesn't pass `?tz=`
+    default_timezone: Tz,
+    seasons: Arc<Vec<Season>>,
+    /// Stats pages taking longer than this are logged as slow
+    slow_request_threshold: Duration,
+    /// Operator-provided banner shown at the top of every HTML page
+    instance_notice: Option<Arc<str>>,
+    placement_points: Arc<PlacementPoints>,
+    bot_accounts: Arc<BotAccounts>,
+    display_aliases: Arc<DisplayAliases>,
+    /// History depth of a stats page that doesn't bound it itself
+    default_fetch_scope: FetchScope,
+    player_id_cache: Arc<PlayerIdCache>,
+    history_cache: Arc<HistoryCache>,
+    /// Set once startup has finished, for `/ready`
+    ready: Arc<AtomicBool>,
+}
+
+/// Remembers name searches, since an account's ID practically never changes.
+struct PlayerIdCache {
+    ttl: Duration,
+    entries: Mutex<BTreeMap<(GameRule, String), CachedPlayerIds>>,
+}
+
+/// When the search ran, and the accounts it returned.
+type CachedPlayerIds = (Instant, Vec<PlayerCandidate>);
+
+/// An account a name search returned, as `(account_id, nickname)`.
+type PlayerCandidate = (u64, String);
+
+impl PlayerIdCache {
+    fn new(ttl: Duration) -> Self {
+        PlayerIdCache {
+            ttl,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn get(&self, rule: GameRule, player_name: &str) -> Option<Vec<PlayerCandidate>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(rule, player_name.to_string()))
+            .filter(|(cached_at, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, player_ids)| player_ids.clone())
+    }
+
+    fn insert(&self, rule: GameRule, player_name: &str, player_ids: Vec<PlayerCandidate>) {
+        let mut entries = self.entries.lock().unwrap();
+        // Expired entries are dropped here so names searched once don't pile up forever
+        entries.retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+        // Nor may distinct names searched within the TTL grow the map without bound
+        let key = (rule, player_name.to_string());
+        if entries.len() >= PLAYER_ID_CACHE_CAPACITY && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (cached_at, _))| *cached_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
         }
+        entries.insert(key, (Instant::now(), player_ids));
     }
 }
 
-#[derive(Debug)]
-struct GameMatch {
-    player_rank: u64,
-    start_time: DateTime<Utc>,
-    duration_minutes: u64,
-    game_type: GameType,
-    pt_change: i64,
-    player_results: Vec<PlayerResult>,
+/// Complete histories across every room of a rule, so reloading a page or switching
+/// its filters doesn't walk the whole history upstream again. Expired entries are kept
+/// until evicted, so a refresh only has to fetch the games played since.
+struct HistoryCache {
+    ttl: Duration,
+    entries: Mutex<BTreeMap<(u64, GameRule), CachedHistory>>,
 }
 
-#[derive(Debug)]
-struct PlayerResult {
-    name: String,
-    final_score: i64,
+/// When the fetch finished, and what it found.
+type CachedHistory = (Instant, Vec<GameMatch>, ParseReport);
+
+impl HistoryCache {
+    fn new(ttl: Duration) -> Self {
+        HistoryCache {
+            ttl,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn get(&self, player_id: u64, rule: GameRule) -> Option<(Vec<GameMatch>, ParseReport)> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(player_id, rule))
+            .filter(|(cached_at, _, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, matches, parse_report)| (matches.clone(), parse_report.clone()))
+    }
+
+    /// The entry regardless of its age, as the base for an incremental refresh.
+    fn get_expired(&self, player_id: u64, rule: GameRule) -> Option<(Vec<GameMatch>, ParseReport)> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(player_id, rule))
+            .map(|(_, matches, parse_report)| (matches.clone(), parse_report.clone()))
+    }
+
+    fn insert(
+        &self,
+        player_id: u64,
+        rule: GameRule,
+        matches: Vec<GameMatch>,
+        parse_report: ParseReport,
+    ) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= HISTORY_CACHE_CAPACITY && !entries.contains_key(&(player_id, rule)) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (cached_at, _, _))| *cached_at)
+                .map(|(&key, _)| key);
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert((player_id, rule), (Instant::now(), matches, parse_report));
+    }
 }
 
+/// Points awarded per placement under `?scoring=uma`, indexed by rank - 1.
 #[derive(Debug)]
-enum GameRule {
-    ThreePlayer,
-    FourPlayer,
+struct PlacementPoints {
+    three_player: Vec<i64>,
+    four_player: Vec<i64>,
 }
 
-impl GameRule {
-    fn api_base_url(&self) -> &'static str {
-        match self {
-            GameRule::ThreePlayer => "https://5-data.amae-koromo.com/api/v2/pl3",
-            GameRule::FourPlayer => "https://5-data.amae-koromo.com/api/v2/pl4",
+impl PlacementPoints {
+    fn for_rule(&self, rule: &GameRule) -> &[i64] {
+        match rule {
+            GameRule::ThreePlayer => &self.three_player,
+            GameRule::FourPlayer => &self.four_player,
         }
     }
+}
 
-    fn supported_mode_ids(&self) -> &'static str {
-        match self {
-            GameRule::ThreePlayer => "21,22,23,24,25,26",
-            GameRule::FourPlayer => "8,9,11,12,15,16",
-        }
+const DEFAULT_PLACEMENT_POINTS_3P: [i64; 3] = [30, 0, -30];
+const DEFAULT_PLACEMENT_POINTS_4P: [i64; 4] = [45, 5, -15, -45];
+
+/// Parses comma-separated points, one per placement starting with 1st.
+fn parse_placement_points(config: &str, rule: GameRule) -> Result<Vec<i64>> {
+    let points = config
+        .split(',')
+        .map(|value| value.trim().parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if points.len() as u64 != rule.player_count() {
+        return Err(anyhow!(
+            "{} needs {} placement points, got {}",
+            rule,
+            rule.player_count(),
+            points.len()
+        ));
     }
+
+    Ok(points)
 }
 
-#[derive(Debug)]
-enum GameCategory {
-    Gold,
-    GoldEast,
-    Jade,
-    JadeEast,
-    Throne,
-    ThroneEast,
+/// Which per-game value PT figures are computed from, selected with `?scoring=`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Scoring {
+    /// The ladder points the game itself awarded
+    #[default]
+    Pt,
+    /// The operator's placement-point table, as leagues often score
+    Uma,
+}
+
+/// A named ladder season, configured by the operator since upstream has no notion of them.
+#[derive(Debug, Clone)]
+struct Season {
+    name: String,
+    first_day: NaiveDate,
+    last_day: NaiveDate,
+}
+
+impl Season {
+    fn contains(&self, date: NaiveDate) -> bool {
+        (self.first_day..=self.last_day).contains(&date)
+    }
+}
+
+/// Parses `NAME=YYYY-MM-DD..YYYY-MM-DD` entries separated by commas, both days inclusive.
+fn parse_seasons(config: &str) -> Result<Vec<Season>> {
+    config
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (name, range) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Season entry is missing '=': {}", entry))?;
+            let (first_day, last_day) = range
+                .split_once("..")
+                .ok_or_else(|| anyhow!("Season range is missing '..': {}", range))?;
+
+            Ok(Season {
+                name: name.trim().to_string(),
+                first_day: first_day.trim().parse()?,
+                last_day: last_day.trim().parse()?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Template)]
+#[template(path = "user_stats.html")]
+struct UserStatsTemplate {
+    language: Language,
+    instance_notice: Option<Arc<str>>,
+    /// Scores and running totals are grouped with this when `?locale=` is given
+    thousands_separator: Option<char>,
+    player_name: String,
+    /// Shown instead of `player_name`, which stays visible on hover
+    player_alias: Option<String>,
+    /// Empty when opponents are anonymized, so their aliases don't give them away
+    display_aliases: Arc<DisplayAliases>,
+    /// Start of the newest fetched game, for showing whether the player is still active
+    last_game_time: Option<DateTime<Utc>>,
+    rank_summary: Option<RankSummary>,
+    /// `None` renders as a "no games" notice
+    player_summary: Option<PlayerSummary>,
+    timezone: Tz,
+    game_history: Vec<GameMatch>,
+    total_matches: usize,
+    partial_history: bool,
+    /// Upstream records that could not be parsed and are missing from the page
+    skipped_records: usize,
+    /// Games dropped for lacking a plausible end time
+    incomplete_games: usize,
+    season: Option<Season>,
+    days: Option<u32>,
+    /// Set when the page is a snapshot of the stats as they stood on this day
+    as_of: Option<NaiveDate>,
+    /// The configured season covering today, offered in the history-depth dropdown
+    current_season: Option<Season>,
+    heatmap: PlayTimeHeatmap,
+    time_of_day_insight: Option<TimeOfDayInsight>,
+    show_opponent_levels: bool,
+    hide_bots: bool,
+    columns: BTreeSet<TableColumn>,
+    average_opponent_level: Option<f64>,
+    pt_per_hour: Option<f64>,
+    time_played: Option<TimePlayed>,
+    pt_change_summary: Option<PtChangeSummary>,
+    rating_estimate: Option<f64>,
+    placement_volatility: Option<f64>,
+    score_outcomes: ScoreOutcomeCounts,
+    placement_sparkline: Option<String>,
+    placement_games: PlacementGames,
+    monthly_trend: Option<MonthlyTrend>,
+    most_played: Option<GameTypeShare>,
+    game_type_breakdown: Vec<GameTypeBreakdown>,
+    game_type_extremes: Option<GameTypeExtremes>,
+    placement_shares: Vec<PlacementShare>,
+    placement_averages: Vec<PlacementAverages>,
+    duration_extremes: Option<DurationExtremes>,
+    longest_gap: Option<LongestGap>,
+    current_streak: CurrentStreak,
+    streak_distribution: StreakDistribution,
+    highlight_pt: Option<i64>,
+    weighted_averages: Option<WeightedAverages>,
+    /// Set when PT figures use placement points instead of the game's PT
+    placement_points: Option<Vec<i64>>,
+    session_summary: Option<SessionSummary>,
 }
 
+/// Totals for a `/today` page, where the whole history is a single session.
 #[derive(Debug)]
-struct GameType {
-    rule: GameRule,
-    category: GameCategory,
+struct SessionSummary {
+    since: DateTime<Utc>,
+    games: usize,
+    net_pt: i64,
+    average_placement: Option<f64>,
 }
 
-impl Display for GameType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let rule_prefix = match self.rule {
-            GameRule::ThreePlayer => "3P",
-            GameRule::FourPlayer => "4P",
-        };
+impl UserStatsTemplate {
+    fn t<'a>(&self, key: &'a str) -> &'a str {
+        self.language.translate(key)
+    }
 
-        let category_name = match self.category {
-            GameCategory::GoldEast => "Gold East",
-            GameCategory::Gold => "Gold",
-            GameCategory::JadeEast => "Jade East",
-            GameCategory::Jade => "Jade",
-            GameCategory::ThroneEast => "Throne East",
-            GameCategory::Throne => "Throne",
+    fn local_time(&self, time: &DateTime<Utc>) -> DateTime<Tz> {
+        time.with_timezone(&self.timezone)
+    }
+
+    /// A coarse "3 hours ago" phrase for how long before now `time` was, shared by the
+    /// last-game line and the match table.
+    fn time_ago(&self, time: &DateTime<Utc>) -> String {
+        let age = Utc::now() - *time;
+        let (amount, key) = if age.num_days() > 0 {
+            (age.num_days(), "days_ago")
+        } else if age.num_hours() > 0 {
+            (age.num_hours(), "hours_ago")
+        } else {
+            (age.num_minutes().max(0), "minutes_ago")
         };
 
-        write!(f, "{} {}", rule_prefix, category_name)
+        self.t(key).replace("{}", &amount.to_string())
     }
-}
 
-impl From<u64> for GameType {
-    fn from(mode_id: u64) -> Self {
-        match mode_id {
-            21 => GameType {
-                rule: GameRule::ThreePlayer,
-                category: GameCategory::GoldEast,
-            },
-            22 => GameType {
-                rule: GameRule::ThreePlayer,
-                category: GameCategory::Gold,
-            },
-            23 => GameType {
-                rule: GameRule::ThreePlayer,
-                category: GameCategory::JadeEast,
-            },
-            24 => GameType {
-                rule: GameRule::ThreePlayer,
-                category: GameCategory::Jade,
-            },
-            25 => GameType {
-                rule: GameRule::ThreePlayer,
-                category: GameCategory::ThroneEast,
-            },
-            26 => GameType {
-                rule: GameRule::ThreePlayer,
-                category: GameCategory::Throne,
-            },
-            8 => GameType {
-                rule: GameRule::FourPlayer,
-                category: GameCategory::GoldEast,
-            },
-            9 => GameType {
-                rule: GameRule::FourPlayer,
-                category: GameCategory::Gold,
-            },
-            11 => GameType {
-                rule: GameRule::FourPlayer,
-                category: GameCategory::JadeEast,
-            },
-            12 => GameType {
-                rule: GameRule::FourPlayer,
-                category: GameCategory::Jade,
-            },
-            15 => GameType {
-                rule: GameRule::FourPlayer,
-                category: GameCategory::ThroneEast,
-            },
-            16 => GameType {
-                rule: GameRule::FourPlayer,
-                category: GameCategory::Throne,
-            },
-            _ => unreachable!("Invalid mode ID: {}", mode_id),
+    fn placement_source_label(&self, game: &GameMatch) -> &str {
+        match game.placement_source {
+            PlacementSource::Upstream => self.t("placement_from_record"),
+            PlacementSource::GradingScore => self.t("placement_from_points"),
+        }
+    }
+
+    fn alias_of(&self, player: &PlayerResult) -> Option<String> {
+        self.display_aliases.get(player.account_id)
+    }
+
+    fn shows(&self, column: TableColumn) -> bool {
+        self.columns.contains(&column)
+    }
+
+    fn is_highlighted(&self, game: &GameMatch) -> bool {
+        self.highlight_pt
+            .is_some_and(|threshold| game.pt_change.abs() >= threshold)
+    }
+
+    fn is_current_season_selected(&self) -> bool {
+        match (&self.season, &self.current_season) {
+            (Some(season), Some(current)) => season.name == current.name,
+            _ => false,
         }
     }
+
+    fn level_label(&self, scale: &f64) -> String {
+        PlayerLevel::from_scale(*scale).to_string()
+    }
 }
 
-async fn find_player_id_by_name(player_name: &str, rule: &GameRule) -> Result<u64> {
-    let search_url = format!(
-        "{}/search_player/{}?tag=all",
-        rule.api_base_url(),
-        player_name
-    );
-    let response = reqwest::get(search_url).await?.json::<Value>().await?;
+mod filters {
+    use askama::Values;
+    use std::fmt::Display;
 
-    if response.is_array() && !response.as_array().unwrap().is_empty() {
-        Ok(response[0]["id"].as_u64().expect("Valid player ID"))
-    } else {
-        Err(anyhow!("No player found with name: {}", player_name))
+    /// Inserts `separator` between groups of three digits; numbers stay plain without one.
+    pub fn grouped<T: Display>(
+        value: T,
+        _: &dyn Values,
+        separator: &Option<char>,
+    ) -> askama::Result<String> {
+        let digits = value.to_string();
+        let Some(separator) = *separator else {
+            return Ok(digits);
+        };
+
+        let (sign, digits) = match digits.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", digits.as_str()),
+        };
+
+        let mut grouped = String::from(sign);
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index) % 3 == 0 {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+
+        Ok(grouped)
+    }
+
+    /// `grouped` with a `+` on positive numbers and zero, as `{:+}` would print them.
+    pub fn signed_grouped<T: Display>(
+        value: T,
+        values: &dyn Values,
+        separator: &Option<char>,
+    ) -> askama::Result<String> {
+        let grouped = grouped(value, values, separator)?;
+        if grouped.starts_with('-') {
+            Ok(grouped)
+        } else {
+            Ok(format!("+{}", grouped))
+        }
     }
 }
 
-async fn handle_3p_player_stats(
-    Path(player_name): Path<String>,
-) -> Result<impl IntoResponse, StatusCode> {
-    handle_player_stats_request(player_name, GameRule::ThreePlayer).await
+/// Digit grouping selected with `?locale=`, independent of the UI language.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum NumberLocale {
+    En,
+    Ja,
+    Zh,
+    De,
+    Fr,
 }
 
-async fn handle_4p_player_stats(
-    Path(player_name): Path<String>,
-) -> Result<impl IntoResponse, StatusCode> {
-    handle_player_stats_request(player_name, GameRule::FourPlayer).await
+impl NumberLocale {
+    fn thousands_separator(&self) -> char {
+        match self {
+            NumberLocale::En | NumberLocale::Ja | NumberLocale::Zh => ',',
+            NumberLocale::De => '.',
+            // Narrow no-break space, as French typography uses
+            NumberLocale::Fr => '\u{202f}',
+        }
+    }
 }
 
-async fn handle_player_stats_request(
+const CARD_BAR_WIDTH: f64 = 328.0;
+const PLACEMENT_COLORS: [&str; 4] = ["#d9534f", "#f0ad4e", "#5bc0de", "#5cb85c"];
+
+#[derive(Template)]
+#[template(path = "stat_card.svg")]
+struct StatCardTemplate {
     player_name: String,
     rule: GameRule,
-) -> Result<impl IntoResponse, StatusCode> {
-    let player_id = find_player_id_by_name(&player_name, &rule)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    let game_history = fetch_complete_match_history(player_id, &rule)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    games: usize,
+    average_placement: Option<f64>,
+    win_rate: Option<f64>,
+    placement_bar: Vec<PlacementBarSegment>,
+}
 
-    let template = UserStatsTemplate {
-        player_name,
-        game_history,
-    };
+/// One placement's share of the card's stacked bar, in SVG units.
+#[derive(Debug)]
+struct PlacementBarSegment {
+    rank: u64,
+    games: u64,
+    offset: f64,
+    width: f64,
+    color: &'static str,
+}
 
-    Ok(HtmlTemplate(template))
+#[derive(Template)]
+#[template(path = "self_compare.html")]
+struct SelfCompareTemplate {
+    language: Language,
+    instance_notice: Option<Arc<str>>,
+    player_name: String,
+    player_alias: Option<String>,
+    period_a: PeriodSummary,
+    period_b: PeriodSummary,
+    rows: Vec<ComparisonRow>,
 }
 
-async fn fetch_complete_match_history(player_id: u64, rule: &GameRule) -> Result<Vec<GameMatch>> {
-    let mut current_timestamp = chrono::Utc::now().timestamp();
-    let mut all_matches = Vec::new();
+impl SelfCompareTemplate {
+    fn t<'a>(&self, key: &'a str) -> &'a str {
+        self.language.translate(key)
+    }
+}
 
-    loop {
-        let api_url = format!(
-            "{}/player_records/{}/{}/1262304000000?limit=500&mode={}&descending=true",
-            rule.api_base_url(),
-            player_id,
-            current_timestamp,
-            rule.supported_mode_ids()
-        );
+/// Periods with fewer games than this are flagged as too small to read much into.
+const LOW_SAMPLE_GAMES: usize = 10;
 
-        let response = reqwest::get(api_url).await?.json::<Value>().await?;
+/// Comparison windows may reach back at most this many days, which also keeps the window
+/// bounds well inside chrono's representable range.
+const MAX_COMPARISON_DAYS: u32 = 36_500;
 
-        let batch_matches = parse_match_data(&response, player_id);
+/// A window of whole days counted back from now, e.g. `last30` or `prev30`.
+#[derive(Debug, Clone)]
+struct ComparisonPeriod {
+    label: String,
+    /// Start of the window, exclusive of games this many days ago or older
+    oldest_days_ago: u32,
+    /// End of the window
+    newest_days_ago: u32,
+}
 
-        if batch_matches.is_empty() {
-            break;
-        }
+impl ComparisonPeriod {
+    /// `lastN` covers the past N days and `prevN` the N days before those.
+    fn parse(token: &str) -> Option<Self> {
+        let (newest_days_ago, oldest_days_ago) = if let Some(days) = token.strip_prefix("last") {
+            let days = days.parse::<u32>().ok()?;
+            (0, days)
+        } else if let Some(days) = token.strip_prefix("prev") {
+            let days = days.parse::<u32>().ok()?;
+            (days, days.checked_mul(2)?)
+        } else {
+            return None;
+        };
 
-        // Update timestamp for next batch
-        current_timestamp = batch_matches.last().unwrap().start_time.timestamp() - 1;
-        all_matches.extend(batch_matches);
+        (oldest_days_ago > newest_days_ago && oldest_days_ago <= MAX_COMPARISON_DAYS).then(|| {
+            ComparisonPeriod {
+                label: token.to_string(),
+                oldest_days_ago,
+                newest_days_ago,
+            }
+        })
+    }
 
-        // Stop if we received less than the limit (last page)
-        if response.as_array().unwrap().len() < 500 {
-            break;
-        }
+    fn overlaps(&self, other: &ComparisonPeriod) -> bool {
+        self.newest_days_ago < other.oldest_days_ago && other.newest_days_ago < self.oldest_days_ago
     }
 
-    Ok(all_matches)
+    fn contains(&self, time: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        let oldest = now - TimeDelta::days(self.oldest_days_ago.into());
+        let newest = now - TimeDelta::days(self.newest_days_ago.into());
+        time > oldest && time <= newest
+    }
 }
 
-fn parse_match_data(api_response: &Value, target_player_id: u64) -> Vec<GameMatch> {
-    api_response
-        .as_array()
-        .unwrap()
-        .iter()
-        .map(|match_data| {
-            let mut player_data = match_data["players"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .map(|player| {
-                    let player_id = player["accountId"].as_u64().unwrap();
-                    let player_name = player["nickname"].as_str().unwrap().to_string();
-                    let final_score = player["score"].as_i64().unwrap();
-                    let pt_change = player["gradingScore"].as_i64().unwrap();
-                    (player_id, player_name, final_score, pt_change)
-                })
-                .collect::<Vec<_>>();
+struct PeriodSummary {
+    label: String,
+    games: usize,
+    low_sample: bool,
+}
 
-            // Sort by pt change (descending) to determine ranking
-            player_data.sort_by(|a, b| b.3.cmp(&a.3));
+/// One metric of the self-comparison, already formatted for display.
+struct ComparisonRow {
+    /// Translation key of the metric name
+    label: &'static str,
+    period_a: String,
+    period_b: String,
+    delta: String,
+}
 
-            let player_rank = player_data
-                .iter()
-                .position(|player| player.0 == target_player_id)
-                .map(|position| (position + 1) as u64)
-                .unwrap();
+#[derive(Template)]
+#[template(path = "embed.html")]
+struct EmbedTemplate {
+    language: Language,
+    player_name: String,
+    player_alias: Option<String>,
+    rule: GameRule,
+    games: usize,
+    average_placement: Option<f64>,
+    win_rate: Option<f64>,
+}
 
-            let raw_start_time = match_data["startTime"].as_u64().unwrap();
-            let raw_end_time = match_data["endTime"].as_u64().unwrap();
+impl EmbedTemplate {
+    fn t<'a>(&self, key: &'a str) -> &'a str {
+        self.language.translate(key)
+    }
+}
 
-            let start_time = DateTime::<Utc>::from_timestamp(raw_start_time as i64, 0).unwrap();
+/// Shown when a name has no account under the requested rule but does under the other.
+#[derive(Template)]
+#[template(path = "other_rule.html")]
+struct OtherRuleTemplate {
+    language: Language,
+    instance_notice: Option<Arc<str>>,
+    player_name: String,
+    rule: GameRule,
+    other_rule: GameRule,
+}
 
-            let duration_minutes = (raw_end_time - raw_start_time) / 60;
+impl OtherRuleTemplate {
+    fn t<'a>(&self, key: &'a str) -> &'a str {
+        self.language.translate(key)
+    }
+}
 
-            let game_type = GameType::from(match_data["modeId"].as_u64().unwrap());
+/// Lists every account a name search matched, each linking to its stats by ID.
+#[derive(Template)]
+#[template(path = "disambiguation.html")]
+struct DisambiguationTemplate {
+    language: Language,
+    instance_notice: Option<Arc<str>>,
+    player_name: String,
+    rule: GameRule,
+    candidates: Vec<PlayerCandidate>,
+}
 
-            let pt_change = player_data
-                .iter()
-                .find(|player| player.0 == target_player_id)
-                .map(|player| player.3)
-                .unwrap();
+impl DisambiguationTemplate {
+    fn t<'a>(&self, key: &'a str) -> &'a str {
+        self.language.translate(key)
+    }
+}
 
-            let player_results = player_data
-                .into_iter()
-                .map(|(_, name, final_score, _)| PlayerResult { name, final_score })
-                .collect();
+/// Explains why a stats page could not be shown, sent with the matching status code.
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate {
+    language: Language,
+    instance_notice: Option<Arc<str>>,
+    status: StatusCode,
+    message: String,
+}
 
-            GameMatch {
-                player_rank,
-                start_time,
-                duration_minutes,
-                game_type,
-                pt_change,
-                player_results,
-            }
-        })
-        .collect()
+impl ErrorTemplate {
+    fn title(&self) -> &str {
+        let key = match self.status {
+            StatusCode::BAD_REQUEST => "error_title_bad_request",
+            StatusCode::NOT_FOUND => "error_title_not_found",
+            StatusCode::INTERNAL_SERVER_ERROR => "error_title_internal",
+            _ => "error_title_upstream",
+        };
+        self.language.translate(key)
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let app = Router::new()
-        .route("/search/3p/{name}", get(handle_3p_player_stats))
-        .route("/search/4p/{name}", get(handle_4p_player_stats));
+struct HtmlTemplate<T>(T);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+impl<T> IntoResponse for HtmlTemplate<T>
+where
+    T: Template,
+{
+    fn into_response(self) -> Response {
+        match self.0.render() {
+            Ok(html_content) => Html(html_content).into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+struct SvgTemplate<T>(T);
+
+impl<T> IntoResponse for SvgTemplate<T>
+where
+    T: Template,
+{
+    fn into_response(self) -> Response {
+        match self.0.render() {
+            Ok(svg_content) => {
+                ([(header::CONTENT_TYPE, "image/svg+xml")], svg_content).into_response()
+            }
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GameMatch {
+    player_rank: u64,
+    start_time: DateTime<Utc>,
+    duration_minutes: u64,
+    game_type: GameType,
+    pt_change: i64,
+    /// Running PT total up to and including this game, over the displayed history
+    cumulative_pt: i64,
+    /// Lead over 2nd place when winning, otherwise the gap to the next-higher player
+    score_margin: i64,
+    average_opponent_level: Option<f64>,
+    player_results: Vec<PlayerResult>,
+    placement_source: PlacementSource,
+}
+
+/// Where a match's placements came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlacementSource {
+    /// An explicit per-player `placement` in the record, which honors seat tiebreaks
+    Upstream,
+    /// Recomputed by sorting on `gradingScore`, breaking ties by final score and then by
+    /// account ID
+    GradingScore,
+}
+
+impl GameMatch {
+    /// The target player's entry in `player_results`, which is ordered by placement.
+    fn own_result(&self) -> &PlayerResult {
+        &self.player_results[(self.player_rank - 1) as usize]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PlayerResult {
+    account_id: u64,
+    name: String,
+    final_score: i64,
+    level: Option<PlayerLevel>,
+    /// Listed in `BOT_IDS` or `BOT_NAMES`, and so left out of opponent stats
+    is_bot: bool,
+}
+
+/// Accounts the operator has flagged as bots through `BOT_IDS` and `BOT_NAMES`.
+#[derive(Debug, Default)]
+struct BotAccounts {
+    ids: BTreeSet<u64>,
+    names: BTreeSet<String>,
+}
+
+impl BotAccounts {
+    fn contains(&self, player: &PlayerResult) -> bool {
+        self.ids.contains(&player.account_id) || self.names.contains(&player.name)
+    }
+
+    /// Flags bot opponents and recomputes each game's opponent level without them, so
+    /// every opponent-based stat downstream leaves them out.
+    fn mark(&self, game_history: &mut [GameMatch], player_id: u64) {
+        if self.ids.is_empty() && self.names.is_empty() {
+            return;
+        }
+
+        for game in game_history {
+            for player in &mut game.player_results {
+                player.is_bot = player.account_id != player_id && self.contains(player);
+            }
+
+            game.average_opponent_level = average_of(
+                game.player_results
+                    .iter()
+                    .filter(|player| player.account_id != player_id && !player.is_bot)
+                    .filter_map(|player| player.level)
+                    .map(|level| level.scale()),
+            );
+        }
+    }
+}
+
+/// Operator-chosen labels from `DISPLAY_ALIASES`, shown in place of nicknames.
+#[derive(Debug, Default)]
+struct DisplayAliases(BTreeMap<u64, String>);
+
+impl DisplayAliases {
+    fn get(&self, account_id: u64) -> Option<String> {
+        self.0.get(&account_id).cloned()
+    }
+}
+
+/// A player's rank when the match was played, decoded from amae-koromo's level id
+/// (e.g. `10402` is 4P Master 2, `20301` is 3P Expert 1).
+#[derive(Debug, Clone, Copy)]
+struct PlayerLevel {
+    tier: u64,
+    step: u64,
+}
+
+impl PlayerLevel {
+    const STEPS_PER_TIER: u64 = 3;
+    const CELESTIAL_TIER: u64 = 6;
+
+    fn from_level_id(level_id: u64) -> Option<Self> {
+        // Celestial was renumbered from tier 6 to 7 at some point; treat both the same
+        let tier = (level_id / 100 % 100).min(Self::CELESTIAL_TIER);
+        let step = level_id % 100;
+
+        if tier == 0 || step == 0 {
+            None
+        } else {
+            Some(PlayerLevel { tier, step })
+        }
+    }
+
+    /// Position on a single ascending scale so levels can be averaged.
+    fn scale(&self) -> f64 {
+        ((self.tier - 1) * Self::STEPS_PER_TIER + self.step - 1) as f64
+    }
+
+    fn from_scale(scale: f64) -> Self {
+        let index = scale.round().max(0.0) as u64;
+        let tier = (index / Self::STEPS_PER_TIER + 1).min(Self::CELESTIAL_TIER);
+        let step = index - (tier - 1) * Self::STEPS_PER_TIER + 1;
+        PlayerLevel { tier, step }
+    }
+}
+
+impl Display for PlayerLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tier_name = match self.tier {
+            1 => "Novice",
+            2 => "Adept",
+            3 => "Expert",
+            4 => "Master",
+            5 => "Saint",
+            _ => "Celestial",
+        };
+
+        write!(f, "{} {}", tier_name, self.step)
+    }
+}
+
+/// The player's rank in their newest game and the highest rank across the fetched history.
+#[derive(Debug)]
+struct RankSummary {
+    current: PlayerLevel,
+    peak: PlayerLevel,
+}
+
+/// `None` when no game records the player's own level.
+fn summarize_rank<'a>(
+    game_history: impl IntoIterator<Item = &'a GameMatch>,
+) -> Option<RankSummary> {
+    let mut levels = game_history
+        .into_iter()
+        .filter_map(|game| game.own_result().level);
+    let current = levels.next()?;
+    let peak = levels.fold(current, |peak, level| {
+        if level.scale() > peak.scale() {
+            level
+        } else {
+            peak
+        }
+    });
+
+    Some(RankSummary { current, peak })
+}
+
+#[derive(Debug)]
+struct GameTypeShare {
+    game_type: GameType,
+    games: usize,
+    percentage: f64,
+}
+
+/// Results in one game type, for the per-room breakdown table.
+#[derive(Debug)]
+struct GameTypeBreakdown {
+    game_type: GameType,
+    games: u64,
+    average_placement: f64,
+    /// Standard deviation of placements; `None` below `LOW_SAMPLE_GAMES` games
+    placement_spread: Option<f64>,
+    first_place_rate: f64,
+    /// Sparkline path of the room's running PT total, oldest game on the left
+    cumulative_pt_path: String,
+}
+
+/// Rooms with the best and worst average placement among those played enough.
+#[derive(Debug)]
+struct GameTypeExtremes {
+    best: GameType,
+    best_average: f64,
+    worst: GameType,
+    worst_average: f64,
+}
+
+/// Rooms with fewer games aren't considered for `GameTypeExtremes`.
+const GAME_TYPE_EXTREMES_MIN_GAMES: u64 = 20;
+
+#[derive(Debug)]
+struct MatchDuration {
+    start_time: DateTime<Utc>,
+    game_type: GameType,
+    duration_minutes: u64,
+}
+
+impl From<&GameMatch> for MatchDuration {
+    fn from(game: &GameMatch) -> Self {
+        MatchDuration {
+            start_time: game.start_time,
+            game_type: game.game_type,
+            duration_minutes: game.duration_minutes,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DurationExtremes {
+    longest: MatchDuration,
+    shortest: MatchDuration,
+}
+
+/// The biggest stretch between two consecutive games' start times.
+#[derive(Debug)]
+struct LongestGap {
+    last_game_before: DateTime<Utc>,
+    first_game_after: DateTime<Utc>,
+}
+
+impl LongestGap {
+    fn days(&self) -> i64 {
+        (self.first_game_after - self.last_game_before).num_days()
+    }
+}
+
+/// Consecutive games up to the most recent one. At most one of the two is non-zero.
+#[derive(Debug)]
+struct CurrentStreak {
+    top_half: usize,
+    last_place: usize,
+}
+
+/// How many runs of each length (two games or more) the history contains, by length.
+#[derive(Debug)]
+struct StreakDistribution {
+    top_half: BTreeMap<usize, usize>,
+    last_place: BTreeMap<usize, usize>,
+}
+
+/// PT change totals and means, overall and split by sign; games with no change only
+/// count overall.
+#[derive(Debug)]
+struct PtChangeSummary {
+    net: i64,
+    gross_gained: i64,
+    gross_lost: i64,
+    overall: f64,
+    gains: Option<f64>,
+    losses: Option<f64>,
+}
+
+/// Parameters of the Tenhou-style rating estimate. Tenhou moves R by
+/// `adjustment * (placement_bonus + (table_average - R) / 40)` per game, where the
+/// adjustment shrinks from 1.0 to 0.2 over a player's first 400 games. Opponent ratings
+/// aren't known here, so every table is assumed to average `RATING_START`.
+const RATING_START: f64 = 1500.0;
+const RATING_SETTLED_GAMES: usize = 400;
+const RATING_SETTLED_ADJUSTMENT: f64 = 0.2;
+const RATING_TABLE_DIVISOR: f64 = 40.0;
+const RATING_BONUSES_3P: [f64; 3] = [30.0, 0.0, -30.0];
+const RATING_BONUSES_4P: [f64; 4] = [30.0, 10.0, -10.0, -30.0];
+
+/// How a player's final scores fall around zero, e.g. for tracking near-busts.
+#[derive(Debug, Default)]
+struct ScoreOutcomeCounts {
+    positive: u64,
+    negative: u64,
+    near_zero: u64,
+}
+
+impl ScoreOutcomeCounts {
+    /// Final scores within this distance of zero count as "near zero".
+    const NEAR_ZERO_RANGE: i64 = 1000;
+}
+
+/// Averages where each full-length (South) game counts `south_weight` times as much as an
+/// East game, mirroring their larger PT stakes.
+#[derive(Debug)]
+struct WeightedAverages {
+    south_weight: f64,
+    placement: f64,
+    pt_change: f64,
+}
+
+/// Match-table columns that `?columns=` can select; the table keeps this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TableColumn {
+    Placement,
+    Time,
+    Duration,
+    Type,
+    Pt,
+    Cumulative,
+    Margin,
+    /// Only shown together with `?opponents=true`
+    Level,
+    Players,
+}
+
+impl TableColumn {
+    const ALL: [TableColumn; 9] = [
+        TableColumn::Placement,
+        TableColumn::Time,
+        TableColumn::Duration,
+        TableColumn::Type,
+        TableColumn::Pt,
+        TableColumn::Cumulative,
+        TableColumn::Margin,
+        TableColumn::Level,
+        TableColumn::Players,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "placement" => Some(TableColumn::Placement),
+            "time" => Some(TableColumn::Time),
+            "duration" => Some(TableColumn::Duration),
+            "type" => Some(TableColumn::Type),
+            "pt" => Some(TableColumn::Pt),
+            "cumulative" => Some(TableColumn::Cumulative),
+            "margin" => Some(TableColumn::Margin),
+            "level" => Some(TableColumn::Level),
+            "players" => Some(TableColumn::Players),
+            _ => None,
+        }
+    }
+}
+
+/// How much history the stats page fetches when the request sets no `days`, `season`,
+/// `as_of` or `/today` bound of its own; explicit bounds always win.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FetchScope {
+    #[default]
+    All,
+    /// The last `RECENT_SCOPE_DAYS` days only
+    Recent,
+}
+
+const RECENT_SCOPE_DAYS: u32 = 90;
+
+/// Games the placement-based stats are computed over, selected with `?placement_games=`.
+/// PT figures always cover every game.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PlacementGames {
+    #[default]
+    All,
+    /// Only full-length (hanchan) games, for comparing placements like for like
+    South,
+}
+
+/// A room regardless of game length, selected with `?category=`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RoomTier {
+    Gold,
+    Jade,
+    Throne,
+}
+
+/// Game length, selected with `?length=`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum GameLength {
+    East,
+    Hanchan,
+}
+
+/// How aggregate stats are weighted, selected with `?weight=`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StatsWeighting {
+    Length,
+}
+
+/// Default factor for South games under `?weight=length`.
+const DEFAULT_SOUTH_WEIGHT: f64 = 2.0;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MatchOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsQuery {
+    tz: Option<String>,
+    opponents: Option<bool>,
+    #[serde(default)]
+    order: MatchOrder,
+    budget_ms: Option<u64>,
+    /// Caps upstream requests; each page holds up to 500 games
+    max_pages: Option<usize>,
+    /// Keeps games without a plausible end time, which skew duration stats
+    include_incomplete: Option<bool>,
+    /// Only games finished in this place are listed in the table
+    placement: Option<u64>,
+    #[serde(default)]
+    scoring: Scoring,
+    #[serde(default)]
+    preset: ModePreset,
+    highlight_pt: Option<i64>,
+    anonymize: Option<bool>,
+    /// Leaves flagged bots out of the table's player lists instead of tagging them
+    hide_bots: Option<bool>,
+    /// Comma-separated `TableColumn` names; every column is shown when absent
+    columns: Option<String>,
+    season: Option<String>,
+    /// Only matches started within this many days are counted
+    days: Option<u32>,
+    /// Overrides the instance's `DEFAULT_FETCH_SCOPE`
+    scope: Option<FetchScope>,
+    /// `YYYY-MM-DD`; only matches started on or before this day are counted
+    as_of: Option<String>,
+    /// Leaves out today's games, so a partly played day doesn't skew trends
+    exclude_today: Option<bool>,
+    /// `YYYY-MM-DD`; only matches started on or after this day are counted
+    from: Option<String>,
+    /// `YYYY-MM-DD`; only matches started on or before this day are counted
+    to: Option<String>,
+    /// Only matches in this room, of either length unless `length` is also given
+    category: Option<RoomTier>,
+    length: Option<GameLength>,
+    weight: Option<StatsWeighting>,
+    #[serde(default)]
+    placement_games: PlacementGames,
+    /// Weight of a South game relative to an East one under `?weight=length`
+    south_weight: Option<f64>,
+    #[serde(default)]
+    lang: Language,
+    locale: Option<NumberLocale>,
+    /// Set by routes such as `/today` rather than the query string
+    #[serde(skip)]
+    since: Option<DateTime<Utc>>,
+    /// Set by `/custom` to count exactly these modes instead of the preset's
+    #[serde(skip)]
+    mode_ids: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CustomModesQuery {
+    /// Comma-separated mode IDs, e.g. `11,12`
+    modes: String,
+}
+
+/// Optional bounds on how much of a player's history is fetched.
+#[derive(Debug, Default)]
+struct FetchLimits {
+    /// No further pages are requested once this has passed
+    deadline: Option<Instant>,
+    /// Only this many of the newest matches are fetched
+    max_matches: Option<usize>,
+    /// Matches started before this are not requested at all
+    since: Option<DateTime<Utc>>,
+    /// Matches started after this are not requested either, for resuming from a cursor
+    until: Option<DateTime<Utc>>,
+    /// At most this many upstream pages are requested, so at most 500 games per page
+    max_pages: Option<usize>,
+    /// Keeps games whose end time is missing or not after their start
+    include_incomplete: bool,
+}
+
+#[derive(Debug)]
+struct FetchedHistory {
+    matches: Vec<GameMatch>,
+    /// Whether a limit stopped the fetch before the oldest match was reached
+    partial: bool,
+    parse_report: ParseReport,
+    /// Upstream pages requested
+    pages: usize,
+}
+
+/// Data-quality summary of a fetch, so dropped records are visible instead of silent.
+#[derive(Debug, Default, Clone)]
+struct ParseReport {
+    /// Upstream records left out because they could not be parsed into a match
+    skipped_records: usize,
+    /// Games without a plausible end time, left out unless asked for
+    incomplete_games: usize,
+}
+
+impl StatsQuery {
+    fn timezone(&self, default: Tz) -> Result<Tz> {
+        match &self.tz {
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|_| anyhow!("Invalid timezone: {}", name)),
+            None => Ok(default),
+        }
+    }
+
+    /// The `from` and `to` days, either of which may be open.
+    fn date_range(&self) -> Result<(Option<NaiveDate>, Option<NaiveDate>)> {
+        let parse = |name: &str, date: &Option<String>| {
+            date.as_ref()
+                .map(|date| {
+                    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                        anyhow!("Invalid {} date: {} (expected YYYY-MM-DD)", name, date)
+                    })
+                })
+                .transpose()
+        };
+        let from = parse("from", &self.from)?;
+        let to = parse("to", &self.to)?;
+
+        if let (Some(from), Some(to)) = (from, to)
+            && from > to
+        {
+            return Err(anyhow!(
+                "The from date {} is after the to date {}",
+                from,
+                to
+            ));
+        }
+
+        Ok((from, to))
+    }
+
+    fn includes_category(&self, category: GameCategory) -> bool {
+        let tier = match category {
+            GameCategory::GoldEast | GameCategory::Gold => RoomTier::Gold,
+            GameCategory::JadeEast | GameCategory::Jade => RoomTier::Jade,
+            GameCategory::ThroneEast | GameCategory::Throne => RoomTier::Throne,
+        };
+        let length = if category.is_east() {
+            GameLength::East
+        } else {
+            GameLength::Hanchan
+        };
+
+        self.category.is_none_or(|wanted| wanted == tier)
+            && self.length.is_none_or(|wanted| wanted == length)
+    }
+
+    fn season(&self, seasons: &[Season]) -> Result<Option<Season>> {
+        self.season
+            .as_ref()
+            .map(|name| {
+                seasons
+                    .iter()
+                    .find(|season| &season.name == name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Unknown season: {}", name))
+            })
+            .transpose()
+    }
+
+    fn columns(&self) -> Result<BTreeSet<TableColumn>> {
+        match &self.columns {
+            Some(names) => names
+                .split(',')
+                .map(|name| {
+                    TableColumn::from_name(name.trim())
+                        .ok_or_else(|| anyhow!("Unknown column: {}", name))
+                })
+                .collect(),
+            None => Ok(TableColumn::ALL.into_iter().collect()),
+        }
+    }
+
+    /// The last day counted, from `as_of` and `exclude_today`, whichever ends earlier.
+    fn as_of(&self, timezone: Tz) -> Result<Option<NaiveDate>> {
+        let as_of = self
+            .as_of
+            .as_ref()
+            .map(|date| {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|_| anyhow!("Invalid as-of date: {}", date))
+            })
+            .transpose()?;
+
+        if !self.exclude_today.unwrap_or(false) {
+            return Ok(as_of);
+        }
+
+        let yesterday = Utc::now().with_timezone(&timezone).date_naive() - TimeDelta::days(1);
+        Ok(Some(as_of.map_or(yesterday, |as_of| as_of.min(yesterday))))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct HeatmapCell {
+    games: u64,
+    placement_sum: u64,
+}
+
+impl HeatmapCell {
+    fn average_placement(&self) -> Option<f64> {
+        if self.games == 0 {
+            None
+        } else {
+            Some(self.placement_sum as f64 / self.games as f64)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HeatmapRow {
+    weekday: Weekday,
+    cells: Vec<HeatmapCell>,
+}
+
+/// Games bucketed by day of week (rows, Monday first) and hour of day (columns).
+#[derive(Debug)]
+struct PlayTimeHeatmap {
+    rows: Vec<HeatmapRow>,
+    max_games: u64,
+}
+
+impl PlayTimeHeatmap {
+    /// Hours per bucket when looking for a time of day that stands out.
+    const INSIGHT_BUCKET_HOURS: usize = 6;
+    /// Buckets with fewer games are too noisy to call out.
+    const INSIGHT_MIN_GAMES: u64 = 10;
+    /// Smallest gap from the overall average placement worth mentioning.
+    const INSIGHT_MIN_DIFFERENCE: f64 = 0.1;
+
+    fn intensity(&self, cell: &HeatmapCell) -> f64 {
+        if self.max_games == 0 {
+            0.0
+        } else {
+            cell.games as f64 / self.max_games as f64
+        }
+    }
+
+    /// The time-of-day bucket whose average placement differs most from the overall one.
+    fn time_of_day_insight(&self) -> Option<TimeOfDayInsight> {
+        let bucket_count = 24 / Self::INSIGHT_BUCKET_HOURS;
+        let mut buckets = vec![HeatmapCell::default(); bucket_count];
+        for cell_by_hour in self.rows.iter().map(|row| row.cells.iter().enumerate()) {
+            for (hour, cell) in cell_by_hour {
+                let bucket = &mut buckets[hour / Self::INSIGHT_BUCKET_HOURS];
+                bucket.games += cell.games;
+                bucket.placement_sum += cell.placement_sum;
+            }
+        }
+
+        let overall = HeatmapCell {
+            games: buckets.iter().map(|bucket| bucket.games).sum(),
+            placement_sum: buckets.iter().map(|bucket| bucket.placement_sum).sum(),
+        };
+        let overall_average = overall.average_placement()?;
+
+        buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, bucket)| bucket.games >= Self::INSIGHT_MIN_GAMES)
+            .filter_map(|(index, bucket)| Some((index, bucket.average_placement()?)))
+            .filter(|(_, average)| {
+                (average - overall_average).abs() >= Self::INSIGHT_MIN_DIFFERENCE
+            })
+            .max_by(|(_, a), (_, b)| {
+                (a - overall_average)
+                    .abs()
+                    .total_cmp(&(b - overall_average).abs())
+            })
+            .map(|(index, bucket_average)| TimeOfDayInsight {
+                first_hour: index * Self::INSIGHT_BUCKET_HOURS,
+                end_hour: (index + 1) * Self::INSIGHT_BUCKET_HOURS,
+                bucket_average,
+                overall_average,
+            })
+    }
+}
+
+/// A stretch of the day where the player places notably better or worse than usual.
+#[derive(Debug)]
+struct TimeOfDayInsight {
+    first_hour: usize,
+    /// Exclusive
+    end_hour: usize,
+    bucket_average: f64,
+    overall_average: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum GameRule {
+    ThreePlayer,
+    FourPlayer,
+}
+
+impl GameRule {
+    fn other(&self) -> GameRule {
+        match self {
+            GameRule::ThreePlayer => GameRule::FourPlayer,
+            GameRule::FourPlayer => GameRule::ThreePlayer,
+        }
+    }
+
+    fn player_count(&self) -> u64 {
+        match self {
+            GameRule::ThreePlayer => 3,
+            GameRule::FourPlayer => 4,
+        }
+    }
+
+    fn api_base_url(&self) -> &'static str {
+        match self {
+            GameRule::ThreePlayer => "https://5-data.amae-koromo.com/api/v2/pl3",
+            GameRule::FourPlayer => "https://5-data.amae-koromo.com/api/v2/pl4",
+        }
+    }
+
+    fn mode_ids(&self) -> impl Iterator<Item = u64> {
+        GAME_MODES
+            .iter()
+            .filter(move |(_, rule, _)| rule == self)
+            .map(|(mode_id, _, _)| *mode_id)
+    }
+
+    fn preset_mode_ids(&self, preset: ModePreset) -> Vec<u64> {
+        GAME_MODES
+            .iter()
+            .filter(|(_, rule, category)| rule == self && preset.includes(*category))
+            .map(|(mode_id, _, _)| *mode_id)
+            .collect()
+    }
+}
+
+/// Named room combinations selectable with `?preset=`. Every preset covers both the
+/// East and full-length variants of its rooms.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ModePreset {
+    #[default]
+    All,
+    GoldOnly,
+    JadeOnly,
+    ThroneOnly,
+    JadeThrone,
+}
+
+impl ModePreset {
+    fn includes(&self, category: GameCategory) -> bool {
+        use GameCategory::*;
+
+        match self {
+            ModePreset::All => true,
+            ModePreset::GoldOnly => matches!(category, Gold | GoldEast),
+            ModePreset::JadeOnly => matches!(category, Jade | JadeEast),
+            ModePreset::ThroneOnly => matches!(category, Throne | ThroneEast),
+            ModePreset::JadeThrone => matches!(category, Jade | JadeEast | Throne | ThroneEast),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum GameCategory {
+    Gold,
+    GoldEast,
+    Jade,
+    JadeEast,
+    Throne,
+    ThroneEast,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct GameType {
+    rule: GameRule,
+    category: GameCategory,
+}
+
+impl GameCategory {
+    fn is_east(&self) -> bool {
+        matches!(
+            self,
+            GameCategory::GoldEast | GameCategory::JadeEast | GameCategory::ThroneEast
+        )
+    }
+}
+
+impl Display for GameRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rule_prefix = match self {
+            GameRule::ThreePlayer => "3P",
+            GameRule::FourPlayer => "4P",
+        };
+
+        write!(f, "{}", rule_prefix)
+    }
+}
+
+impl Display for GameCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let category_name = match self {
+            GameCategory::GoldEast => "Gold East",
+            GameCategory::Gold => "Gold",
+            GameCategory::JadeEast => "Jade East",
+            GameCategory::Jade => "Jade",
+            GameCategory::ThroneEast => "Throne East",
+            GameCategory::Throne => "Throne",
+        };
+
+        write!(f, "{}", category_name)
+    }
+}
+
+impl Display for GameType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.rule, self.category)
+    }
+}
+
+/// Every room we fetch and parse, as `(mode_id, rule, category)`. Both directions of
+/// the mode ID mapping are derived from this table.
+const GAME_MODES: &[(u64, GameRule, GameCategory)] = &[
+    (21, GameRule::ThreePlayer, GameCategory::GoldEast),
+    (22, GameRule::ThreePlayer, GameCategory::Gold),
+    (23, GameRule::ThreePlayer, GameCategory::JadeEast),
+    (24, GameRule::ThreePlayer, GameCategory::Jade),
+    (25, GameRule::ThreePlayer, GameCategory::ThroneEast),
+    (26, GameRule::ThreePlayer, GameCategory::Throne),
+    (8, GameRule::FourPlayer, GameCategory::GoldEast),
+    (9, GameRule::FourPlayer, GameCategory::Gold),
+    (11, GameRule::FourPlayer, GameCategory::JadeEast),
+    (12, GameRule::FourPlayer, GameCategory::Jade),
+    (15, GameRule::FourPlayer, GameCategory::ThroneEast),
+    (16, GameRule::FourPlayer, GameCategory::Throne),
+];
+
+/// A mode ID missing from `GAME_MODES`, e.g. a room added upstream after this build.
+#[derive(Debug)]
+struct UnknownModeId(u64);
+
+impl Display for UnknownModeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown mode ID: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownModeId {}
+
+impl TryFrom<u64> for GameType {
+    type Error = UnknownModeId;
+
+    fn try_from(mode_id: u64) -> Result<Self, Self::Error> {
+        GAME_MODES
+            .iter()
+            .find(|(id, _, _)| *id == mode_id)
+            .map(|&(_, rule, category)| GameType { rule, category })
+            .ok_or(UnknownModeId(mode_id))
+    }
+}
+
+impl GameType {
+    /// The inverse of `GameType::try_from`.
+    fn mode_id(&self) -> u64 {
+        GAME_MODES
+            .iter()
+            .find(|&&(_, rule, category)| (rule, category) == (self.rule, self.category))
+            .map(|&(mode_id, _, _)| mode_id)
+            .expect("check_game_modes verifies every game type has a mode ID")
+    }
+}
+
+/// Fails fast at startup if `GAME_MODES` lists a mode ID twice or a `(rule, category)` pair
+/// under two IDs, either of which would make the lookups above ambiguous.
+fn check_game_modes() {
+    for (index, &(mode_id, rule, category)) in GAME_MODES.iter().enumerate() {
+        for &(other_id, other_rule, other_category) in &GAME_MODES[index + 1..] {
+            assert!(
+                other_id != mode_id,
+                "Mode ID {} is listed more than once in GAME_MODES",
+                mode_id
+            );
+            assert!(
+                (other_rule, other_category) != (rule, category),
+                "{} {} is mapped to both mode {} and mode {}",
+                rule,
+                category,
+                mode_id,
+                other_id
+            );
+        }
+
+        let game_type =
+            GameType::try_from(mode_id).unwrap_or_else(|error| panic!("{} in GAME_MODES", error));
+        assert!(
+            rule.mode_ids().any(|id| id == mode_id)
+                && game_type.rule == rule
+                && game_type.category == category
+                && game_type.mode_id() == mode_id,
+            "Mode ID {} does not round-trip through GameType",
+            mode_id
+        );
+    }
+}
+
+/// A name search that matched several accounts, for routes that can only show one.
+#[derive(Debug)]
+struct AmbiguousPlayerName(Vec<PlayerCandidate>);
+
+impl Display for AmbiguousPlayerName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} players use this name", self.0.len())
+    }
+}
+
+impl std::error::Error for AmbiguousPlayerName {}
+
+/// The one account a name search matched. Several matches fail with
+/// `AmbiguousPlayerName` instead of guessing, which would show the wrong player.
+async fn find_player_id_by_name(
+    state: &AppState,
+    player_name: &str,
+    rule: &GameRule,
+) -> Result<u64> {
+    let mut player_ids = find_player_ids_by_name(state, player_name, rule).await?;
+    if player_ids.len() > 1 {
+        return Err(AmbiguousPlayerName(player_ids).into());
+    }
+    Ok(player_ids.remove(0).0)
+}
+
+/// Returns every distinct account the search matched, in upstream order.
+async fn find_player_ids_by_name(
+    state: &AppState,
+    player_name: &str,
+    rule: &GameRule,
+) -> Result<Vec<PlayerCandidate>> {
+    if let Some(player_ids) = state.player_id_cache.get(*rule, player_name) {
+        return Ok(player_ids);
+    }
+
+    let search_url = format!(
+        "{}/search_player/{}?tag=all",
+        rule.api_base_url(),
+        player_name
+    );
+    let response = get_json_with_retry(&state.http_client, &search_url).await?;
+
+    let player_ids = parse_search_response(&response, player_name)?;
+    state
+        .player_id_cache
+        .insert(*rule, player_name, player_ids.clone());
+
+    Ok(player_ids)
+}
+
+fn parse_search_response(response: &Value, player_name: &str) -> Result<Vec<PlayerCandidate>> {
+    match response {
+        Value::Array(players) => {
+            if players.is_empty() {
+                return Err(anyhow!("No player found with name: {}", player_name));
+            }
+
+            // Results without a numeric ID can't be looked up, so they are passed over
+            let mut player_ids = Vec::<PlayerCandidate>::new();
+            for player in players {
+                let Some(player_id) = player["id"].as_u64() else {
+                    continue;
+                };
+                if !player_ids
+                    .iter()
+                    .any(|(known_id, _)| *known_id == player_id)
+                {
+                    let nickname = player["nickname"].as_str().unwrap_or(player_name);
+                    player_ids.push((player_id, nickname.to_string()));
+                }
+            }
+
+            if player_ids.is_empty() {
+                return Err(anyhow!(
+                    "No search result for {} has a valid ID",
+                    player_name
+                ));
+            }
+
+            Ok(player_ids)
+        }
+        // Still an upstream failure, but its own message says more than the shape does
+        Value::Object(body) if body.contains_key("error") => {
+            warn!(player_name, error = %body["error"], "Search returned an error object");
+            Err(anyhow::Error::new(UnexpectedUpstreamFormat)
+                .context(format!("Upstream search error: {}", body["error"])))
+        }
+        _ => {
+            warn!(player_name, response = %response, "Search response is not an array");
+            Err(UnexpectedUpstreamFormat.into())
+        }
+    }
+}
+
+/// A name with no search results is a 404 and one matching several accounts a 409,
+/// while an upstream that can't be reached or answers in an unexpected shape is a 502.
+fn search_error_status(error: &anyhow::Error) -> StatusCode {
+    if error.is::<UnexpectedUpstreamFormat>() || error.is::<reqwest::Error>() {
+        StatusCode::BAD_GATEWAY
+    } else if error.is::<AmbiguousPlayerName>() {
+        StatusCode::CONFLICT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// A route failure's response: usually a bare status, but with a body where the caller
+/// needs one, such as the accounts an ambiguous name matched.
+struct RouteError(Response);
+
+impl From<StatusCode> for RouteError {
+    fn from(status: StatusCode) -> Self {
+        RouteError(status.into_response())
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        self.0
+    }
+}
+
+/// Each candidate with the stats page that shows it by ID.
+fn candidates_json(rule: GameRule, candidates: &[PlayerCandidate]) -> Value {
+    candidates
+        .iter()
+        .map(|(player_id, nickname)| {
+            json!({
+                "player_id": player_id,
+                "nickname": nickname,
+                "url": format!("/search/{}/id/{}", rule.to_string().to_lowercase(), player_id),
+            })
+        })
+        .collect()
+}
+
+/// A 409 listing the accounts an ambiguous name matched, so clients can tell which one
+/// they meant.
+fn ambiguous_name_error(
+    rule: GameRule,
+    candidates: &[PlayerCandidate],
+) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::CONFLICT,
+        Json(json!({
+            "error": "Several players use this name",
+            "candidates": candidates_json(rule, candidates),
+        })),
+    )
+}
+
+/// A failed search for JSON and download routes, with the candidates of an ambiguous name.
+fn search_error_json(error: &anyhow::Error, rule: GameRule) -> RouteError {
+    match error.downcast_ref::<AmbiguousPlayerName>() {
+        Some(AmbiguousPlayerName(candidates)) => {
+            RouteError(ambiguous_name_error(rule, candidates).into_response())
+        }
+        None => search_error_status(error).into(),
+    }
+}
+
+/// A failed search for HTML routes, answering an ambiguous name with the disambiguation
+/// page.
+fn search_error_page(
+    state: &AppState,
+    error: &anyhow::Error,
+    player_name: String,
+    rule: GameRule,
+    language: Language,
+) -> RouteError {
+    match error.downcast_ref::<AmbiguousPlayerName>() {
+        Some(AmbiguousPlayerName(candidates)) => RouteError(disambiguation_page(
+            state,
+            player_name,
+            rule,
+            language,
+            candidates.clone(),
+        )),
+        None => search_error_status(error).into(),
+    }
+}
+
+/// A 409 letting the user pick which of the accounts sharing a name they meant.
+fn disambiguation_page(
+    state: &AppState,
+    player_name: String,
+    rule: GameRule,
+    language: Language,
+    candidates: Vec<PlayerCandidate>,
+) -> Response {
+    (
+        StatusCode::CONFLICT,
+        HtmlTemplate(DisambiguationTemplate {
+            language,
+            instance_notice: state.instance_notice.clone(),
+            player_name,
+            rule,
+            candidates,
+        }),
+    )
+        .into_response()
+}
+
+async fn handle_supported_modes() -> Json<Value> {
+    let modes_for = |rule: GameRule| {
+        rule.mode_ids()
+            .map(|mode_id| {
+                let game_type =
+                    GameType::try_from(mode_id).expect("mode IDs come straight from GAME_MODES");
+                json!({
+                    "mode_id": mode_id,
+                    "category": game_type.category.to_string(),
+                    "name": game_type.to_string(),
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+
+    Json(json!({
+        "3p": modes_for(GameRule::ThreePlayer),
+        "4p": modes_for(GameRule::FourPlayer),
+    }))
+}
+
+async fn handle_3p_player_stats(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_player_stats_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_player_stats(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_player_stats_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+async fn handle_3p_today(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_today_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_today(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_today_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+/// The stats page limited to games started since midnight in the viewer's timezone.
+async fn handle_today_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    mut query: StatsQuery,
+) -> Result<Response, StatusCode> {
+    let error_page = |status: StatusCode, message: String| {
+        Ok(error_message_page(
+            state.instance_notice.clone(),
+            query.lang,
+            status,
+            message,
+        ))
+    };
+
+    let timezone = match query.timezone(state.default_timezone) {
+        Ok(timezone) => timezone,
+        Err(error) => return error_page(StatusCode::BAD_REQUEST, error.to_string()),
+    };
+
+    let midnight = Utc::now()
+        .with_timezone(&timezone)
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_local_timezone(timezone)
+        .earliest();
+    let Some(midnight) = midnight else {
+        return error_page(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Today's midnight doesn't exist in {}", timezone),
+        );
+    };
+    query.since = Some(midnight.with_timezone(&Utc));
+
+    handle_player_stats_request(state, player_name, rule, query).await
+}
+
+async fn handle_3p_custom_modes(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(modes): Query<CustomModesQuery>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_custom_modes_request(state, player_name, GameRule::ThreePlayer, modes, query).await
+}
+
+async fn handle_4p_custom_modes(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(modes): Query<CustomModesQuery>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_custom_modes_request(state, player_name, GameRule::FourPlayer, modes, query).await
+}
+
+/// The stats page over an arbitrary set of modes, all of which must belong to the rule.
+async fn handle_custom_modes_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    modes: CustomModesQuery,
+    mut query: StatsQuery,
+) -> Result<Response, StatusCode> {
+    let mut mode_ids = Vec::new();
+    let mut invalid_modes = Vec::new();
+    for mode in modes.modes.split(',').map(str::trim) {
+        match mode.parse::<u64>() {
+            Ok(mode_id) if rule.mode_ids().any(|known| known == mode_id) => {
+                if !mode_ids.contains(&mode_id) {
+                    mode_ids.push(mode_id);
+                }
+            }
+            _ => invalid_modes.push(mode),
+        }
+    }
+
+    if !invalid_modes.is_empty() || mode_ids.is_empty() {
+        return Ok(error_message_page(
+            state.instance_notice.clone(),
+            query.lang,
+            StatusCode::BAD_REQUEST,
+            format!("Invalid modes for this rule: {}", invalid_modes.join(", ")),
+        ));
+    }
+
+    query.mode_ids = Some(mode_ids);
+
+    handle_player_stats_request(state, player_name, rule, query).await
+}
+
+async fn handle_3p_player_lookup(
+    State(state): State<AppState>,
+    Path(lookup): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_player_lookup_request(state, lookup, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_player_lookup(
+    State(state): State<AppState>,
+    Path(lookup): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    handle_player_lookup_request(state, lookup, GameRule::FourPlayer, query).await
+}
+
+/// Purely numeric input is treated as an account ID, anything else as a nickname.
+/// Players whose nickname is all digits must use the `/search/{rule}/{name}` route.
+async fn handle_player_lookup_request(
+    state: AppState,
+    lookup: String,
+    rule: GameRule,
+    query: StatsQuery,
+) -> Result<Response, StatusCode> {
+    match lookup.parse::<u64>() {
+        Ok(player_id) => Ok(render_player_stats_page(state, player_id, None, rule, query).await),
+        Err(_) => handle_player_stats_request(state, lookup, rule, query).await,
+    }
+}
+
+async fn handle_player_stats_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: StatsQuery,
+) -> Result<Response, StatusCode> {
+    let language = query.lang;
+    let error_page = |status: StatusCode| {
+        let not_found = language
+            .translate("error_player_not_found")
+            .replace("{}", &player_name);
+        Ok(stats_error_page(
+            state.instance_notice.clone(),
+            language,
+            status,
+            not_found,
+        ))
+    };
+
+    if player_name.chars().count() > MAX_PLAYER_NAME_CHARS {
+        return error_page(StatusCode::BAD_REQUEST);
+    }
+
+    let mut player_ids = match find_player_ids_by_name(&state, &player_name, &rule).await {
+        Ok(player_ids) => player_ids,
+        Err(error) if search_error_status(&error) == StatusCode::BAD_GATEWAY => {
+            return error_page(StatusCode::BAD_GATEWAY);
+        }
+        Err(_) => {
+            // A name known only to the other rule is a common mix-up worth pointing out
+            let other_rule = rule.other();
+            return match find_player_ids_by_name(&state, &player_name, &other_rule).await {
+                Ok(_) => Ok((
+                    StatusCode::NOT_FOUND,
+                    HtmlTemplate(OtherRuleTemplate {
+                        language,
+                        instance_notice: state.instance_notice.clone(),
+                        player_name,
+                        rule,
+                        other_rule,
+                    }),
+                )
+                    .into_response()),
+                Err(_) => error_page(StatusCode::NOT_FOUND),
+            };
+        }
+    };
+
+    // Guessing between accounts that share a name would show the wrong player's stats
+    if player_ids.len() > 1 {
+        return Ok(disambiguation_page(
+            &state,
+            player_name,
+            rule,
+            language,
+            player_ids,
+        ));
+    }
+    let (player_id, _) = player_ids.remove(0);
+
+    Ok(render_player_stats_page(state, player_id, Some(player_name), rule, query).await)
+}
+
+async fn handle_3p_player_stats_by_id(
+    State(state): State<AppState>,
+    Path(player_id): Path<u64>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, StatusCode> {
+    Ok(render_player_stats_page(state, player_id, None, GameRule::ThreePlayer, query).await)
+}
+
+async fn handle_4p_player_stats_by_id(
+    State(state): State<AppState>,
+    Path(player_id): Path<u64>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, StatusCode> {
+    Ok(render_player_stats_page(state, player_id, None, GameRule::FourPlayer, query).await)
+}
+
+/// Explains a failed stats page with a message for its status; `not_found` is shown for
+/// a 404, naming whoever was looked up.
+fn stats_error_page(
+    instance_notice: Option<Arc<str>>,
+    language: Language,
+    status: StatusCode,
+    not_found: String,
+) -> Response {
+    let message = match status {
+        StatusCode::NOT_FOUND => not_found,
+        StatusCode::BAD_REQUEST => language.translate("error_bad_request").to_owned(),
+        _ => language.translate("error_upstream").to_owned(),
+    };
+
+    error_message_page(instance_notice, language, status, message)
+}
+
+/// An error page saying exactly what went wrong, such as which parameter was invalid.
+fn error_message_page(
+    instance_notice: Option<Arc<str>>,
+    language: Language,
+    status: StatusCode,
+    message: String,
+) -> Response {
+    (
+        status,
+        HtmlTemplate(ErrorTemplate {
+            language,
+            instance_notice,
+            status,
+            message,
+        }),
+    )
+        .into_response()
+}
+
+/// `render_player_stats` with any failure shown through `stats_error_page`.
+async fn render_player_stats_page(
+    state: AppState,
+    player_id: u64,
+    player_name: Option<String>,
+    rule: GameRule,
+    query: StatsQuery,
+) -> Response {
+    let language = query.lang;
+    let instance_notice = state.instance_notice.clone();
+    let not_found = match &player_name {
+        Some(name) => language
+            .translate("error_player_not_found")
+            .replace("{}", name),
+        None => language
+            .translate("error_player_id_not_found")
+            .replace("{}", &player_id.to_string()),
+    };
+
+    match render_player_stats(state, player_id, player_name, rule, query).await {
+        Ok(response) => response,
+        Err(status) => stats_error_page(instance_notice, language, status, not_found),
+    }
+}
+
+/// Renders the stats page for a resolved account. The displayed name is the nickname
+/// from the player's most recent match, falling back to `player_name` without matches.
+async fn render_player_stats(
+    state: AppState,
+    player_id: u64,
+    player_name: Option<String>,
+    rule: GameRule,
+    query: StatsQuery,
+) -> Result<Response, StatusCode> {
+    let started = Instant::now();
+
+    let (from, to) = match query.date_range() {
+        Ok(range) => range,
+        Err(error) => {
+            return Ok(error_message_page(
+                state.instance_notice.clone(),
+                query.lang,
+                StatusCode::BAD_REQUEST,
+                error.to_string(),
+            ));
+        }
+    };
+
+    let timezone = query
+        .timezone(state.default_timezone)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let season = query
+        .season(&state.seasons)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let as_of = query.as_of(timezone).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let columns = query.columns().map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let south_weight = query.south_weight.unwrap_or(DEFAULT_SOUTH_WEIGHT);
+    if !south_weight.is_finite() || south_weight <= 0.0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if query
+        .placement
+        .is_some_and(|placement| !(1..=rule.player_count()).contains(&placement))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let scope = query.scope.unwrap_or(state.default_fetch_scope);
+    let scoped_days = (scope == FetchScope::Recent
+        && query.days.is_none()
+        && season.is_none()
+        && query.as_of.is_none()
+        && from.is_none()
+        && to.is_none()
+        && query.since.is_none())
+    .then_some(RECENT_SCOPE_DAYS);
+    let days = query.days.or(scoped_days);
+
+    // A budget too large to represent as an instant is rejected rather than panicking
+    let deadline = query
+        .budget_ms
+        .map(|budget| {
+            Instant::now()
+                .checked_add(Duration::from_millis(budget))
+                .ok_or(StatusCode::BAD_REQUEST)
+        })
+        .transpose()?;
+
+    let limits = FetchLimits {
+        deadline,
+        // A recent-only default is there for speed, so older pages aren't requested at all
+        since: query.since.or(scoped_days
+            .and_then(|days| Utc::now().checked_sub_signed(TimeDelta::days(days.into())))),
+        max_pages: query.max_pages,
+        include_incomplete: query.include_incomplete.unwrap_or(false),
+        ..FetchLimits::default()
+    };
+
+    let mode_ids = query
+        .mode_ids
+        .clone()
+        .unwrap_or_else(|| rule.preset_mode_ids(query.preset));
+
+    let fetched_history = fetch_match_history(&state, player_id, &rule, &mode_ids, &limits)
+        .await
+        .map_err(|error| fetch_error_status(&error))?;
+    let partial_history = fetched_history.partial;
+    let skipped_records = fetched_history.parse_report.skipped_records;
+    let incomplete_games = fetched_history.parse_report.incomplete_games;
+    let fetched_pages = fetched_history.pages;
+    let mut game_history = fetched_history.matches;
+    state.bot_accounts.mark(&mut game_history, player_id);
+
+    // Taken before the other windows, so the peak covers every fetched game up to `as_of`
+    let rank_summary = summarize_rank(
+        game_history
+            .iter()
+            .filter(|game| played_by(game, as_of, timezone)),
+    );
+
+    // Accounts can be renamed, so the nickname from the latest match wins over the search input
+    let player_name = latest_nickname(&game_history, player_id)
+        .or(player_name)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Also before any window, so "last played" means the same whatever the filters
+    let last_game_time = game_history.iter().map(|game| game.start_time).max();
+
+    retain_history_window(&mut game_history, season.as_ref(), days, as_of, timezone);
+    game_history.retain(|game| {
+        let day = game.start_time.with_timezone(&timezone).date_naive();
+        from.is_none_or(|from| day >= from)
+            && to.is_none_or(|to| day <= to)
+            && query.includes_category(game.game_type.category)
+    });
+
+    // Everything PT-based below, from totals to the cumulative column, follows the scoring
+    let placement_points = match query.scoring {
+        Scoring::Pt => None,
+        Scoring::Uma => Some(state.placement_points.for_rule(&rule).to_vec()),
+    };
+    if let Some(points) = &placement_points {
+        for game in &mut game_history {
+            game.pt_change = points[(game.player_rank - 1) as usize];
+        }
+    }
+
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+    let current_season = state
+        .seasons
+        .iter()
+        .find(|season| season.contains(today))
+        .cloned();
+
+    annotate_cumulative_pt(&mut game_history);
+
+    let player_summary = summarize_player(&game_history, &rule);
+
+    let heatmap = build_play_time_heatmap(&game_history, timezone);
+    let time_of_day_insight = heatmap.time_of_day_insight();
+
+    // Levels come with every record, so the flag only controls whether they are shown
+    let show_opponent_levels = query.opponents.unwrap_or(false);
+    let average_opponent_level = average_of(
+        game_history
+            .iter()
+            .filter_map(|game| game.average_opponent_level),
+    );
+
+    let pt_per_hour = pt_per_hour(&game_history);
+    let time_played = time_played(&game_history);
+    let pt_change_summary = summarize_pt_changes(&game_history);
+    let score_outcomes = count_score_outcomes(&game_history);
+    let most_played = most_played_game_type(&game_history);
+    let game_type_breakdown = break_down_by_game_type(&game_history);
+    let game_type_extremes = find_game_type_extremes(&game_type_breakdown);
+
+    // The placement-based aggregates run a second time over their own subset when asked
+    let south_history;
+    let placement_history = match query.placement_games {
+        PlacementGames::All => &game_history,
+        PlacementGames::South => {
+            south_history = game_history
+                .iter()
+                .filter(|game| !game.game_type.category.is_east())
+                .cloned()
+                .collect::<Vec<_>>();
+            &south_history
+        }
+    };
+    let rating_estimate = estimate_rating(placement_history, &rule);
+    let placement_volatility = placement_volatility(placement_history);
+    let placement_sparkline = placement_sparkline_path(placement_history, &rule);
+    let monthly_trend = monthly_placement_trend(placement_history, &rule, timezone);
+    let placement_shares = if placement_history.is_empty() {
+        Vec::new()
+    } else {
+        placement_shares(placement_history, &rule)
+    };
+    let placement_averages = average_by_placement(&game_history, &rule);
+    let duration_extremes = find_duration_extremes(&game_history);
+    let longest_gap = find_longest_gap(&game_history);
+    let current_streak = find_current_streak(&game_history, &rule);
+    let streak_distribution = count_streaks(&game_history, &rule);
+    let weighted_averages = match query.weight {
+        Some(StatsWeighting::Length) => length_weighted_averages(&game_history, south_weight),
+        None => None,
+    };
+
+    let session_summary = query.since.map(|since| SessionSummary {
+        since,
+        games: game_history.len(),
+        net_pt: game_history.iter().map(|game| game.pt_change).sum(),
+        average_placement: average_of(game_history.iter().map(|game| game.player_rank as f64)),
+    });
+
+    // Everything above covers the full history; only the table is filtered and capped
+    if let Some(placement) = query.placement {
+        game_history.retain(|game| game.player_rank == placement);
+    }
+
+    let total_matches = game_history.len();
+    game_history.truncate(state.max_rendered_matches);
+
+    let anonymize = query.anonymize.unwrap_or(false);
+    if anonymize {
+        anonymize_opponents(&mut game_history, player_id);
+    }
+
+    // The history is newest first; only the final displayed list is reversed
+    if let MatchOrder::Asc = query.order {
+        game_history.reverse();
+    }
+
+    let elapsed = started.elapsed();
+    if elapsed > state.slow_request_threshold {
+        warn!(
+            player_id,
+            player_name,
+            games = total_matches,
+            pages = fetched_pages,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Slow stats request"
+        );
+    }
+
+    let template = UserStatsTemplate {
+        language: query.lang,
+        instance_notice: state.instance_notice.clone(),
+        thousands_separator: query.locale.map(|locale| locale.thousands_separator()),
+        player_name,
+        player_alias: state.display_aliases.get(player_id),
+        display_aliases: if anonymize {
+            Arc::new(DisplayAliases::default())
+        } else {
+            state.display_aliases.clone()
+        },
+        last_game_time,
+        rank_summary,
+        player_summary,
+        timezone,
+        game_history,
+        total_matches,
+        partial_history,
+        skipped_records,
+        incomplete_games,
+        season,
+        days,
+        as_of,
+        current_season,
+        heatmap,
+        time_of_day_insight,
+        show_opponent_levels,
+        hide_bots: query.hide_bots.unwrap_or(false),
+        columns,
+        average_opponent_level,
+        pt_per_hour,
+        time_played,
+        pt_change_summary,
+        rating_estimate,
+        placement_volatility,
+        score_outcomes,
+        placement_sparkline,
+        placement_games: query.placement_games,
+        monthly_trend,
+        most_played,
+        game_type_breakdown,
+        game_type_extremes,
+        placement_shares,
+        placement_averages,
+        duration_extremes,
+        longest_gap,
+        current_streak,
+        streak_distribution,
+        highlight_pt: query.highlight_pt,
+        weighted_averages,
+        placement_points,
+        session_summary,
+    };
+
+    Ok(HtmlTemplate(template).into_response())
+}
+
+async fn handle_3p_csv_export(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+) -> Result<Response, RouteError> {
+    handle_csv_export_request(state, player_name, GameRule::ThreePlayer).await
+}
+
+async fn handle_4p_csv_export(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+) -> Result<Response, RouteError> {
+    handle_csv_export_request(state, player_name, GameRule::FourPlayer).await
+}
+
+async fn handle_3p_summary_export(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, RouteError> {
+    handle_summary_export_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_summary_export(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, RouteError> {
+    handle_summary_export_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+#[derive(Debug, Deserialize)]
+struct SelfCompareQuery {
+    period_a: Option<String>,
+    period_b: Option<String>,
+    #[serde(default)]
+    lang: Language,
+}
+
+async fn handle_3p_self_compare(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<SelfCompareQuery>,
+) -> Result<HtmlTemplate<SelfCompareTemplate>, RouteError> {
+    handle_self_compare_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_self_compare(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<SelfCompareQuery>,
+) -> Result<HtmlTemplate<SelfCompareTemplate>, RouteError> {
+    handle_self_compare_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+/// Puts a player's stats over two recent windows side by side; deltas are A minus B.
+async fn handle_self_compare_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: SelfCompareQuery,
+) -> Result<HtmlTemplate<SelfCompareTemplate>, RouteError> {
+    let period_a = ComparisonPeriod::parse(query.period_a.as_deref().unwrap_or("last30"))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let period_b = ComparisonPeriod::parse(query.period_b.as_deref().unwrap_or("prev30"))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    if period_a.overlaps(&period_b) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| {
+            search_error_page(&state, &error, player_name.clone(), rule, query.lang)
+        })?;
+
+    let fetched_history = fetch_match_history(
+        &state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &FetchLimits::default(),
+    )
+    .await
+    .map_err(|error| fetch_error_status(&error))?;
+    let game_history = fetched_history.matches;
+
+    let player_name = latest_nickname(&game_history, player_id).unwrap_or(player_name);
+
+    let now = Utc::now();
+    let games_in = |period: &ComparisonPeriod| {
+        game_history
+            .iter()
+            .filter(|game| period.contains(game.start_time, now))
+            .collect::<Vec<_>>()
+    };
+    let games_a = games_in(&period_a);
+    let games_b = games_in(&period_b);
+
+    let average_placement =
+        |games: &[&GameMatch]| average_of(games.iter().map(|game| game.player_rank as f64));
+    let first_rate = |games: &[&GameMatch]| {
+        average_of(
+            games
+                .iter()
+                .map(|game| if game.player_rank == 1 { 100.0 } else { 0.0 }),
+        )
+    };
+    let average_pt =
+        |games: &[&GameMatch]| average_of(games.iter().map(|game| game.pt_change as f64));
+    let total_pt =
+        |games: &[&GameMatch]| Some(games.iter().map(|game| game.pt_change).sum::<i64>() as f64);
+
+    let row = |label, metric: &dyn Fn(&[&GameMatch]) -> Option<f64>, precision: usize| {
+        let value_a = metric(&games_a);
+        let value_b = metric(&games_b);
+        let format_value = |value: Option<f64>| {
+            value.map_or("-".to_string(), |value| format!("{:.*}", precision, value))
+        };
+
+        ComparisonRow {
+            label,
+            period_a: format_value(value_a),
+            period_b: format_value(value_b),
+            delta: match (value_a, value_b) {
+                (Some(a), Some(b)) => format!("{:+.*}", precision, a - b),
+                _ => "-".to_string(),
+            },
+        }
+    };
+
+    let rows = vec![
+        row("average_placement", &average_placement, 2),
+        row("first_place_rate", &first_rate, 1),
+        row("average_pt_change", &average_pt, 1),
+        row("total_pt_change", &total_pt, 0),
+    ];
+
+    let summary = |period: ComparisonPeriod, games: usize| PeriodSummary {
+        label: period.label,
+        games,
+        low_sample: games < LOW_SAMPLE_GAMES,
+    };
+
+    Ok(HtmlTemplate(SelfCompareTemplate {
+        language: query.lang,
+        instance_notice: state.instance_notice.clone(),
+        player_name,
+        player_alias: state.display_aliases.get(player_id),
+        period_a: summary(period_a, games_a.len()),
+        period_b: summary(period_b, games_b.len()),
+        rows,
+    }))
+}
+
+async fn handle_3p_placements(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_placements_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_placements(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_placements_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+/// Placement counts and percentages, honoring the same `preset`, `season` and `days`
+/// filters as the stats page.
+async fn handle_placements_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: StatsQuery,
+) -> Result<Json<Value>, RouteError> {
+    let timezone = query
+        .timezone(state.default_timezone)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let season = query
+        .season(&state.seasons)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let as_of = query.as_of(timezone).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_json(&error, rule))?;
+
+    let fetched_history = fetch_match_history(
+        &state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(query.preset),
+        &FetchLimits::default(),
+    )
+    .await
+    .map_err(|error| fetch_error_status(&error))?;
+    let mut game_history = fetched_history.matches;
+
+    retain_history_window(
+        &mut game_history,
+        season.as_ref(),
+        query.days,
+        as_of,
+        timezone,
+    );
+
+    let games = game_history.len();
+    let placements = placement_shares(&game_history, &rule)
+        .into_iter()
+        .map(|share| {
+            json!({
+                "rank": share.rank,
+                "games": share.games,
+                "percentage": share.percentage,
+                "neutral_percentage": share.neutral_percentage,
+                "delta": share.delta(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "player_id": player_id,
+        "rule": rule.to_string(),
+        "games": games,
+        "placements": placements,
+    })))
+}
+
+/// Upper bound on `?n=` for the recent-matches API.
+const MAX_RECENT_MATCHES: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct RecentMatchesQuery {
+    n: Option<usize>,
+    #[serde(default)]
+    time_format: TimeFormat,
+    /// A previous response's `next_cursor`, to continue with the matches before it
+    cursor: Option<String>,
+}
+
+/// Opaque to clients: the start time, in Unix seconds, of the newest match still to be
+/// returned, base64-encoded so it can be passed back as-is.
+fn encode_match_cursor(start_time: DateTime<Utc>) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(start_time.timestamp().to_string())
+}
+
+fn decode_match_cursor(cursor: &str) -> Result<DateTime<Utc>> {
+    let decoded = BASE64_URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| anyhow!("Invalid cursor: {}", cursor))?;
+    String::from_utf8(decoded)
+        .ok()
+        .and_then(|timestamp| timestamp.parse::<i64>().ok())
+        .and_then(|timestamp| DateTime::<Utc>::from_timestamp(timestamp, 0))
+        .ok_or_else(|| anyhow!("Invalid cursor: {}", cursor))
+}
+
+/// How JSON responses encode match times, selected with `?time_format=`.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TimeFormat {
+    /// RFC 3339 in UTC, e.g. `"2025-06-01T12:34:56+00:00"`
+    #[default]
+    Iso,
+    /// Unix seconds, as amae-koromo itself reports them, e.g. `1748781296`
+    Unix,
+}
+
+async fn handle_3p_recent_matches(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<RecentMatchesQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_recent_matches_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_recent_matches(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<RecentMatchesQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_recent_matches_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+async fn handle_3p_player_api(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<PlayerApiQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    handle_player_api_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_player_api(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<PlayerApiQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    handle_player_api_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerApiQuery {
+    #[serde(default)]
+    time_format: TimeFormat,
+}
+
+/// An error status with a `{"error": ...}` body, so API clients can tell failures apart.
+fn api_error(status: StatusCode, message: &str) -> (StatusCode, Json<Value>) {
+    (status, Json(json!({ "error": message })))
+}
+
+/// The full match history as JSON, for bots and scripts rather than browsers.
+async fn handle_player_api_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: PlayerApiQuery,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if player_name.chars().count() > MAX_PLAYER_NAME_CHARS {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            "Player name is too long",
+        ));
+    }
+
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| match search_error_status(&error) {
+            StatusCode::NOT_FOUND => api_error(StatusCode::NOT_FOUND, "Player not found"),
+            StatusCode::CONFLICT => match error.downcast_ref::<AmbiguousPlayerName>() {
+                Some(AmbiguousPlayerName(candidates)) => ambiguous_name_error(rule, candidates),
+                None => api_error(StatusCode::CONFLICT, "Several players use this name"),
+            },
+            status => api_error(status, "Player search failed upstream"),
+        })?;
+
+    let fetched_history = fetch_match_history(
+        &state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &FetchLimits::default(),
+    )
+    .await
+    .map_err(|error| api_error(fetch_error_status(&error), "Fetching matches failed"))?;
+
+    let player_name = latest_nickname(&fetched_history.matches, player_id).unwrap_or(player_name);
+    let matches = fetched_history
+        .matches
+        .iter()
+        .map(|game| match_json(game, query.time_format))
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "player_name": player_name,
+        "player_id": player_id,
+        "rule": rule.to_string(),
+        "matches": matches,
+    })))
+}
+
+/// Returns only the newest matches, stopping the fetch once enough have arrived.
+async fn handle_recent_matches_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: RecentMatchesQuery,
+) -> Result<Json<Value>, RouteError> {
+    let match_count = query.n.unwrap_or(5).clamp(1, MAX_RECENT_MATCHES);
+    let until = query
+        .cursor
+        .as_deref()
+        .map(decode_match_cursor)
+        .transpose()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_json(&error, rule))?;
+
+    let limits = FetchLimits {
+        max_matches: Some(match_count),
+        until,
+        ..FetchLimits::default()
+    };
+
+    let fetched_history = fetch_match_history(
+        &state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &limits,
+    )
+    .await
+    .map_err(|error| fetch_error_status(&error))?;
+
+    let matches = fetched_history
+        .matches
+        .iter()
+        .map(|game| match_json(game, query.time_format))
+        .collect::<Vec<_>>();
+
+    // Only a fetch cut short by `n` can have older matches left
+    let next_cursor = fetched_history
+        .partial
+        .then(|| fetched_history.matches.last())
+        .flatten()
+        .map(|oldest| encode_match_cursor(oldest.start_time - TimeDelta::seconds(1)));
+
+    Ok(Json(json!({
+        "player_id": player_id,
+        "matches": matches,
+        "next_cursor": next_cursor,
+    })))
+}
+
+/// `start_time` is a string or a number depending on `time_format`.
+fn match_json(game: &GameMatch, time_format: TimeFormat) -> Value {
+    let start_time = match time_format {
+        TimeFormat::Iso => json!(game.start_time.to_rfc3339()),
+        TimeFormat::Unix => json!(game.start_time.timestamp()),
+    };
+
+    json!({
+        "start_time": start_time,
+        "game_type": game.game_type.to_string(),
+        "rank": game.player_rank,
+        "pt_change": game.pt_change,
+        "duration_minutes": game.duration_minutes,
+        "players": game
+            .player_results
+            .iter()
+            .map(|player| json!({ "name": player.name, "score": player.final_score }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Opponents listed by `/opponents` unless `?limit=` asks for more or fewer.
+const DEFAULT_OPPONENT_LIMIT: usize = 20;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OpponentOrder {
+    /// Most games together first
+    #[default]
+    Count,
+    Name,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpponentsQuery {
+    limit: Option<usize>,
+    #[serde(default)]
+    sort: OpponentOrder,
+}
+
+async fn handle_3p_opponents(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<OpponentsQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_opponents_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_opponents(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<OpponentsQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_opponents_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+/// Every distinct account the player has shared a table with, and how often.
+async fn handle_opponents_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: OpponentsQuery,
+) -> Result<Json<Value>, RouteError> {
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_json(&error, rule))?;
+
+    let fetched_history = fetch_match_history(
+        &state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &FetchLimits::default(),
+    )
+    .await
+    .map_err(|error| fetch_error_status(&error))?;
+
+    let mut game_history = fetched_history.matches;
+    state.bot_accounts.mark(&mut game_history, player_id);
+
+    let mut opponents = count_opponents(&game_history, player_id);
+    match query.sort {
+        OpponentOrder::Count => opponents.sort_by_key(|opponent| Reverse(opponent.games)),
+        OpponentOrder::Name => opponents.sort_by(|a, b| a.name.cmp(&b.name)),
+    }
+
+    let total_opponents = opponents.len();
+    let opponents = opponents
+        .iter()
+        .take(query.limit.unwrap_or(DEFAULT_OPPONENT_LIMIT))
+        .map(|opponent| {
+            json!({
+                "account_id": opponent.account_id,
+                "name": opponent.name,
+                "games": opponent.games,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "player_id": player_id,
+        "rule": rule.to_string(),
+        "total_opponents": total_opponents,
+        "opponents": opponents,
+    })))
+}
+
+#[derive(Debug)]
+struct OpponentCount {
+    account_id: u64,
+    /// The nickname from the newest shared game, since accounts can be renamed
+    name: String,
+    games: u64,
+}
+
+/// Tallies everyone but the target player and flagged bots across a newest-first history, ordered by
+/// account ID.
+fn count_opponents(game_history: &[GameMatch], player_id: u64) -> Vec<OpponentCount> {
+    let mut opponents = BTreeMap::<u64, OpponentCount>::new();
+    for game in game_history {
+        for result in &game.player_results {
+            if result.account_id == player_id || result.is_bot {
+                continue;
+            }
+
+            opponents
+                .entry(result.account_id)
+                .or_insert_with(|| OpponentCount {
+                    account_id: result.account_id,
+                    name: result.name.clone(),
+                    games: 0,
+                })
+                .games += 1;
+        }
+    }
+
+    opponents.into_values().collect()
+}
+
+/// Largest roster `/api/team` accepts, since every member costs a full history fetch.
+const MAX_TEAM_MEMBERS: usize = 20;
+
+/// Members fetched at once, so one roster can't take over the upstream connection pool.
+const TEAM_FETCH_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Deserialize)]
+struct TeamRequest {
+    members: Vec<String>,
+}
+
+async fn handle_3p_team(
+    State(state): State<AppState>,
+    Json(team): Json<TeamRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    handle_team_request(state, GameRule::ThreePlayer, team).await
+}
+
+async fn handle_4p_team(
+    State(state): State<AppState>,
+    Json(team): Json<TeamRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    handle_team_request(state, GameRule::FourPlayer, team).await
+}
+
+/// Combined placements and PT across a roster. A member who can't be found or fetched,
+/// or whose name matches several accounts, is reported with their status and left out of
+/// the totals rather than failing the roster.
+async fn handle_team_request(
+    state: AppState,
+    rule: GameRule,
+    team: TeamRequest,
+) -> Result<Json<Value>, StatusCode> {
+    if team.members.is_empty() || team.members.len() > MAX_TEAM_MEMBERS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mode_ids = rule.preset_mode_ids(ModePreset::All);
+    let fetches = team.members.into_iter().map(|player_name| {
+        let state = &state;
+        let mode_ids = &mode_ids;
+        async move {
+            let history = match find_player_id_by_name(state, &player_name, &rule).await {
+                Ok(player_id) => {
+                    fetch_match_history(state, player_id, &rule, mode_ids, &FetchLimits::default())
+                        .await
+                        .map(|fetched_history| (player_id, fetched_history.matches))
+                        .map_err(|error| (fetch_error_status(&error), None))
+                }
+                Err(error) => Err((
+                    search_error_status(&error),
+                    error
+                        .downcast_ref::<AmbiguousPlayerName>()
+                        .map(|AmbiguousPlayerName(candidates)| candidates_json(rule, candidates)),
+                )),
+            };
+            (player_name, history)
+        }
+    });
+    // Buffered in roster order, so members come back in the order they were sent
+    let results = stream::iter(fetches)
+        .buffered(TEAM_FETCH_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut team_history = Vec::new();
+    let mut members = Vec::new();
+    for (player_name, history) in results {
+        match history {
+            Ok((player_id, game_history)) => {
+                members.push(json!({
+                    "name": player_name,
+                    "status": "ok",
+                    "player_id": player_id,
+                    "games": game_history.len(),
+                    "total_pt": game_history.iter().map(|game| game.pt_change).sum::<i64>(),
+                    "placements": count_placements(&game_history, &rule),
+                }));
+                team_history.extend(game_history);
+            }
+            // Guessing between accounts that share a name would count the wrong games
+            Err((_, Some(candidates))) => members.push(json!({
+                "name": player_name,
+                "status": "ambiguous",
+                "candidates": candidates,
+            })),
+            Err((status, None)) => members.push(json!({
+                "name": player_name,
+                "status": if status == StatusCode::NOT_FOUND { "not_found" } else { "upstream_error" },
+            })),
+        }
+    }
+
+    let placements = placement_shares(&team_history, &rule)
+        .into_iter()
+        .map(|share| {
+            json!({
+                "rank": share.rank,
+                "games": share.games,
+                "percentage": share.percentage,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(json!({
+        "rule": rule.to_string(),
+        "games": team_history.len(),
+        "total_pt": team_history.iter().map(|game| game.pt_change).sum::<i64>(),
+        "placements": placements,
+        "members": members,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct DebugPageQuery {
+    /// Cursor in seconds, as `match_records_url` takes it and `next_page` returns it;
+    /// defaults to now
+    before: Option<i64>,
+    #[serde(default)]
+    time_format: TimeFormat,
+}
+
+async fn handle_3p_debug_page(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<DebugPageQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_debug_page_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_debug_page(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<DebugPageQuery>,
+) -> Result<Json<Value>, RouteError> {
+    handle_debug_page_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+/// Fetches exactly one page at the given cursor and reports the cursor the full fetch
+/// would use next, to inspect pagination boundaries in isolation.
+async fn handle_debug_page_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: DebugPageQuery,
+) -> Result<Json<Value>, RouteError> {
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_json(&error, rule))?;
+
+    let mode_list = rule
+        .mode_ids()
+        .map(|mode_id| mode_id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let cursor = query.before.unwrap_or_else(|| Utc::now().timestamp());
+    let api_url = match_records_url(&rule, player_id, cursor, 1262304000000, 500, &mode_list);
+
+    let response = get_json_with_retry(&state.http_client, &api_url)
+        .await
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    let records = response.as_array().ok_or(StatusCode::BAD_GATEWAY)?;
+
+    // Raw pages are for inspecting upstream, so nothing parseable is held back
+    let mut parse_report = ParseReport::default();
+    let matches = parse_match_data(records, player_id, true, &mut parse_report);
+    let next_page = next_page(records, 500, cursor);
+    let next_cursor = match next_page {
+        NextPage::Before(before) => Some(before),
+        NextPage::Done | NextPage::Stuck => None,
+    };
+
+    Ok(Json(json!({
+        "player_id": player_id,
+        "cursor": cursor,
+        "records": records.len(),
+        "skipped_records": parse_report.skipped_records,
+        "next_cursor": next_cursor,
+        "stuck": next_page == NextPage::Stuck,
+        "matches": matches
+            .iter()
+            .map(|game| match_json(game, query.time_format))
+            .collect::<Vec<_>>(),
+    })))
+}
+
+const CSV_HEADER: &str = "start_time,game_type,rank,pt_change,duration_minutes,players\n";
+
+/// Streams the history as CSV while it is fetched, writing each upstream page's rows
+/// before requesting the next, so only one page is held at a time however long the
+/// history is. A history already in the cache is written out from there instead.
+async fn handle_csv_export_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+) -> Result<Response, RouteError> {
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_json(&error, rule))?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"majsoul-stats-{}.csv\"", player_id),
+        ),
+    ];
+
+    if let Some((matches, _)) = state.history_cache.get(player_id, rule) {
+        let rows = std::iter::once(CSV_HEADER.to_string())
+            .chain(matches.into_iter().map(|game| csv_row(&game)))
+            .map(Ok::<_, Infallible>);
+        return Ok((headers, Body::from_stream(stream::iter(rows))).into_response());
+    }
+
+    let client = state.http_client.clone();
+    let mode_list = rule
+        .preset_mode_ids(ModePreset::All)
+        .iter()
+        .map(|mode_id| mode_id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    // The first page is fetched before answering, so an unreachable upstream still gets
+    // a proper status; a later page failing can only cut the download short
+    let (first_rows, next_before) =
+        fetch_csv_page(&client, player_id, rule, &mode_list, Utc::now().timestamp())
+            .await
+            .map_err(|error| fetch_error_status(&error))?;
+
+    let later_rows = stream::try_unfold(next_before, move |next_before| {
+        let client = client.clone();
+        let mode_list = mode_list.clone();
+        async move {
+            let Some(before) = next_before else {
+                return Ok(None);
+            };
+            let page = fetch_csv_page(&client, player_id, rule, &mode_list, before).await?;
+            Ok::<_, anyhow::Error>(Some(page))
+        }
+    });
+    let rows = stream::once(std::future::ready(Ok(CSV_HEADER.to_string() + &first_rows)))
+        .chain(later_rows);
+
+    Ok((headers, Body::from_stream(rows)).into_response())
+}
+
+/// One page of CSV rows for records started at or before `before`, and where the next
+/// page starts, if there is one.
+async fn fetch_csv_page(
+    client: &reqwest::Client,
+    player_id: u64,
+    rule: GameRule,
+    mode_list: &str,
+    before: i64,
+) -> Result<(String, Option<i64>)> {
+    let api_url = match_records_url(&rule, player_id, before, 1262304000000, 500, mode_list);
+    let response = get_json_with_retry(client, &api_url).await?;
+    let Some(records) = response.as_array() else {
+        warn!(response = %response, "Match records response is not an array");
+        return Err(UnexpectedUpstreamFormat.into());
+    };
+
+    let rows = parse_match_data(records, player_id, false, &mut ParseReport::default())
+        .iter()
+        .map(csv_row)
+        .collect::<String>();
+
+    let next_before = match next_page(records, 500, before) {
+        NextPage::Done => None,
+        NextPage::Before(next_before) => Some(next_before),
+        NextPage::Stuck => {
+            warn!(
+                player_id,
+                "Full page of match records has no start time to continue from"
+            );
+            None
+        }
+    };
+
+    Ok((rows, next_before))
+}
+
+/// The stats page's aggregates without the match list, as a dated download.
+async fn handle_summary_export_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: StatsQuery,
+) -> Result<Response, RouteError> {
+    let timezone = query
+        .timezone(state.default_timezone)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let season = query
+        .season(&state.seasons)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let as_of = query.as_of(timezone).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let player_id = find_player_id_by_name(&state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_json(&error, rule))?;
+
+    let fetched_history = fetch_match_history(
+        &state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(query.preset),
+        &FetchLimits::default(),
+    )
+    .await
+    .map_err(|error| fetch_error_status(&error))?;
+    let mut game_history = fetched_history.matches;
+    state.bot_accounts.mark(&mut game_history, player_id);
+
+    let rank_summary = summarize_rank(
+        game_history
+            .iter()
+            .filter(|game| played_by(game, as_of, timezone)),
+    );
+
+    retain_history_window(
+        &mut game_history,
+        season.as_ref(),
+        query.days,
+        as_of,
+        timezone,
+    );
+
+    let placements = placement_shares(&game_history, &rule)
+        .into_iter()
+        .map(|share| {
+            json!({
+                "rank": share.rank,
+                "games": share.games,
+                "percentage": share.percentage,
+            })
+        })
+        .collect::<Vec<_>>();
+    let placement_averages = average_by_placement(&game_history, &rule)
+        .into_iter()
+        .map(|averages| {
+            json!({
+                "rank": averages.rank,
+                "games": averages.games,
+                "average_final_score": averages.average_final_score,
+                "average_pt_change": averages.average_pt_change,
+            })
+        })
+        .collect::<Vec<_>>();
+    let game_types = break_down_by_game_type(&game_history)
+        .into_iter()
+        .map(|breakdown| {
+            json!({
+                "game_type": breakdown.game_type.to_string(),
+                "games": breakdown.games,
+                "average_placement": breakdown.average_placement,
+                "placement_spread": breakdown.placement_spread,
+                "first_place_rate": breakdown.first_place_rate,
+            })
+        })
+        .collect::<Vec<_>>();
+    let pt_changes = summarize_pt_changes(&game_history).map(|summary| {
+        json!({
+            "net": summary.net,
+            "gross_gained": summary.gross_gained,
+            "gross_lost": summary.gross_lost,
+            "average": summary.overall,
+            "average_gain": summary.gains,
+            "average_loss": summary.losses,
+        })
+    });
+    let score_outcomes = count_score_outcomes(&game_history);
+    let current_streak = find_current_streak(&game_history, &rule);
+    let streak_distribution = count_streaks(&game_history, &rule);
+
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+    let summary = json!({
+        "player_id": player_id,
+        "player_name": latest_nickname(&game_history, player_id).unwrap_or(player_name),
+        "rule": rule.to_string(),
+        "generated_on": today.to_string(),
+        "games": game_history.len(),
+        "rank": rank_summary.map(|rank| json!({
+            "current": rank.current.to_string(),
+            "peak": rank.peak.to_string(),
+        })),
+        "average_placement": average_of(game_history.iter().map(|game| game.player_rank as f64)),
+        "rating_estimate": estimate_rating(&game_history, &rule),
+        "placement_volatility": placement_volatility(&game_history),
+        "average_opponent_level": average_of(
+            game_history.iter().filter_map(|game| game.average_opponent_level),
+        ),
+        "minutes_played": time_played(&game_history).map(|time| time.total_minutes),
+        "pt_per_hour": pt_per_hour(&game_history),
+        "pt_changes": pt_changes,
+        "final_scores": {
+            "positive": score_outcomes.positive,
+            "negative": score_outcomes.negative,
+            "near_zero": score_outcomes.near_zero,
+        },
+        "placements": placements,
+        "placement_averages": placement_averages,
+        "game_types": game_types,
+        "streaks": {
+            "current_top_half": current_streak.top_half,
+            "current_last_place": current_streak.last_place,
+            "top_half_runs": streak_distribution.top_half,
+            "last_place_runs": streak_distribution.last_place,
+        },
+    });
+
+    let content_disposition = format!(
+        "attachment; filename=\"majsoul-stats-{}-summary-{}.json\"",
+        player_id, today
+    );
+
+    Ok((
+        [(header::CONTENT_DISPOSITION, content_disposition)],
+        Json(summary),
+    )
+        .into_response())
+}
+
+fn csv_row(game: &GameMatch) -> String {
+    let players = game
+        .player_results
+        .iter()
+        .map(|player| format!("{}({})", player.name, player.final_score))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "{},{},{},{},{},{}\n",
+        game.start_time.to_rfc3339(),
+        game.game_type,
+        game.player_rank,
+        game.pt_change,
+        game.duration_minutes,
+        csv_field(&players)
+    )
+}
+
+/// Quotes a field when it contains characters that would break the row.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+async fn handle_3p_stat_card(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<impl IntoResponse, RouteError> {
+    handle_stat_card_request(state, file_name, GameRule::ThreePlayer).await
+}
+
+async fn handle_4p_stat_card(
+    State(state): State<AppState>,
+    Path(file_name): Path<String>,
+) -> Result<impl IntoResponse, RouteError> {
+    handle_stat_card_request(state, file_name, GameRule::FourPlayer).await
+}
+
+/// Serves `/card/{rule}/{name}.svg`. The router can't match a parameter with a static
+/// suffix, so the whole segment is captured and the extension checked here.
+async fn handle_stat_card_request(
+    state: AppState,
+    file_name: String,
+    rule: GameRule,
+) -> Result<SvgTemplate<StatCardTemplate>, RouteError> {
+    let player_name = file_name
+        .strip_suffix(".svg")
+        .ok_or(StatusCode::NOT_FOUND)?
+        .to_string();
+
+    let summary = fetch_compact_summary(&state, player_name, rule, Language::default()).await?;
+    let games = summary.games;
+
+    let mut offset = 0.0;
+    let placement_bar = summary
+        .placement_counts
+        .iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .map(|(index, &count)| {
+            let width = count as f64 / games as f64 * CARD_BAR_WIDTH;
+            let segment = PlacementBarSegment {
+                rank: index as u64 + 1,
+                games: count,
+                offset,
+                width,
+                color: PLACEMENT_COLORS[index],
+            };
+            offset += width;
+            segment
+        })
+        .collect();
+
+    Ok(SvgTemplate(StatCardTemplate {
+        // An image has no hover, so the alias simply replaces the nickname
+        player_name: summary.player_alias.unwrap_or(summary.player_name),
+        rule,
+        games,
+        average_placement: summary.average_placement,
+        win_rate: summary.win_rate,
+        placement_bar,
+    }))
+}
+
+/// The handful of figures the stat card and the embed widget show.
+struct CompactSummary {
+    player_name: String,
+    player_alias: Option<String>,
+    games: usize,
+    average_placement: Option<f64>,
+    win_rate: Option<f64>,
+    placement_counts: Vec<u64>,
+}
+
+async fn fetch_compact_summary(
+    state: &AppState,
+    player_name: String,
+    rule: GameRule,
+    language: Language,
+) -> Result<CompactSummary, RouteError> {
+    let player_id = find_player_id_by_name(state, &player_name, &rule)
+        .await
+        .map_err(|error| search_error_page(state, &error, player_name.clone(), rule, language))?;
+
+    let fetched_history = fetch_match_history(
+        state,
+        player_id,
+        &rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &FetchLimits::default(),
+    )
+    .await
+    .map_err(|error| fetch_error_status(&error))?;
+    let game_history = fetched_history.matches;
+
+    let player_name = latest_nickname(&game_history, player_id).unwrap_or(player_name);
+    let placement_counts = count_placements(&game_history, &rule);
+    let games = game_history.len();
+
+    let average_placement = average_of(game_history.iter().map(|game| game.player_rank as f64));
+    let win_rate = (games > 0).then(|| placement_counts[0] as f64 / games as f64 * 100.0);
+
+    Ok(CompactSummary {
+        player_name,
+        player_alias: state.display_aliases.get(player_id),
+        games,
+        average_placement,
+        win_rate,
+        placement_counts,
+    })
+}
+
+async fn handle_3p_embed(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<EmbedQuery>,
+) -> Result<Response, RouteError> {
+    handle_embed_request(state, player_name, GameRule::ThreePlayer, query).await
+}
+
+async fn handle_4p_embed(
+    State(state): State<AppState>,
+    Path(player_name): Path<String>,
+    Query(query): Query<EmbedQuery>,
+) -> Result<Response, RouteError> {
+    handle_embed_request(state, player_name, GameRule::FourPlayer, query).await
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbedQuery {
+    #[serde(default)]
+    lang: Language,
+}
+
+/// A compact fragment for iframes on other sites, the only page that allows being framed
+/// from anywhere.
+async fn handle_embed_request(
+    state: AppState,
+    player_name: String,
+    rule: GameRule,
+    query: EmbedQuery,
+) -> Result<Response, RouteError> {
+    let summary = fetch_compact_summary(&state, player_name, rule, query.lang).await?;
+
+    let template = EmbedTemplate {
+        language: query.lang,
+        player_name: summary.player_name,
+        player_alias: summary.player_alias,
+        rule,
+        games: summary.games,
+        average_placement: summary.average_placement,
+        win_rate: summary.win_rate,
+    };
+
+    Ok((
+        [(header::CONTENT_SECURITY_POLICY, "frame-ancestors *")],
+        HtmlTemplate(template),
+    )
+        .into_response())
+}
+
+/// Top-level keys every match record must carry for `parse_match_data` to make sense of it.
+const EXPECTED_RECORD_KEYS: [&str; 3] = ["players", "modeId", "startTime"];
+
+/// Upstream answered, but not in the shape this server expects, which usually means
+/// amae-koromo changed its response format.
+#[derive(Debug)]
+struct UnexpectedUpstreamFormat;
+
+impl Display for UnexpectedUpstreamFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Upstream response format may have changed")
+    }
+}
+
+impl std::error::Error for UnexpectedUpstreamFormat {}
+
+/// Retries after the first failed attempt; with the base delay doubling each time, a
+/// request gives up after 200ms + 400ms + 800ms of waiting.
+const UPSTREAM_RETRIES: u32 = 3;
+const UPSTREAM_RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Upstream answered 404, which for a search means there is no such player.
+#[derive(Debug)]
+struct UpstreamNotFound;
+
+impl Display for UpstreamNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Upstream returned 404 Not Found")
+    }
+}
+
+impl std::error::Error for UpstreamNotFound {}
+
+/// GETs `url` as JSON, retrying connection failures and 5xx responses with exponential
+/// backoff. Other error statuses are final, a 404 as `UpstreamNotFound`.
+async fn get_json_with_retry(client: &reqwest::Client, url: &str) -> Result<Value> {
+    let mut attempt = 0;
+    loop {
+        let error = match client.get(url).send().await {
+            Ok(response) if response.status().is_server_error() => {
+                response.error_for_status().unwrap_err()
+            }
+            Ok(response) if response.status() == StatusCode::NOT_FOUND => {
+                return Err(UpstreamNotFound.into());
+            }
+            Ok(response) => return Ok(response.error_for_status()?.json::<Value>().await?),
+            Err(error) => error,
+        };
+
+        if attempt >= UPSTREAM_RETRIES {
+            return Err(error.into());
+        }
+        let delay = UPSTREAM_RETRY_BASE_DELAY * 2u32.pow(attempt);
+        attempt += 1;
+        warn!(
+            url,
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            error = %error,
+            "Upstream request failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Upstream format changes surface as 502 so they aren't mistaken for our own bugs.
+fn fetch_error_status(error: &anyhow::Error) -> StatusCode {
+    if error.is::<UnexpectedUpstreamFormat>() {
+        StatusCode::BAD_GATEWAY
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Checks the first page of records against `EXPECTED_RECORD_KEYS`, logging what
+/// arrived instead so a format change is quick to diagnose. Only a page on which no
+/// record has them counts as a format change; a lone odd record is left to
+/// `parse_single_match` to skip.
+fn probe_record_schema(response: &Value) -> Result<()> {
+    let Some(records) = response.as_array() else {
+        warn!(response = %response, "Match records response is not an array");
+        return Err(UnexpectedUpstreamFormat.into());
+    };
+
+    let Some(record) = records.first() else {
+        return Ok(());
+    };
+
+    let has_expected_keys = |record: &Value| {
+        EXPECTED_RECORD_KEYS
+            .iter()
+            .all(|key| record.get(key).is_some())
+    };
+    if !records.iter().any(has_expected_keys) {
+        let keys = record
+            .as_object()
+            .map(|fields| fields.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        warn!(?keys, expected = ?EXPECTED_RECORD_KEYS, "Match records are missing expected keys");
+        return Err(UnexpectedUpstreamFormat.into());
+    }
+
+    Ok(())
+}
+
+/// One page of records started at or before `before` (seconds) and not before `oldest`
+/// (milliseconds), newest first.
+fn match_records_url(
+    rule: &GameRule,
+    player_id: u64,
+    before: i64,
+    oldest: i64,
+    page_size: usize,
+    mode_list: &str,
+) -> String {
+    format!(
+        "{}/player_records/{}/{}/{}?limit={}&mode={}&descending=true",
+        rule.api_base_url(),
+        player_id,
+        before,
+        oldest,
+        page_size,
+        mode_list
+    )
+}
+
+/// Where paging goes after a page of raw records.
+#[derive(Debug, PartialEq, Eq)]
+enum NextPage {
+    /// Receiving less than the page size means this was the last page, which for most
+    /// players is also the first, so no cursor is needed
+    Done,
+    /// Records started at or before this (seconds) come next
+    Before(i64),
+    /// A full page offered no start time older than the one it was requested with
+    Stuck,
+}
+
+/// The cursor is taken from the raw records rather than the parsed matches, so records
+/// that fail to parse neither end paging early nor get fetched again.
+fn next_page(records: &[Value], page_size: usize, before: i64) -> NextPage {
+    if records.len() < page_size {
+        return NextPage::Done;
+    }
+
+    records
+        .iter()
+        .filter_map(|record| record["startTime"].as_i64())
+        .min()
+        .map(|oldest_start| oldest_start - 1)
+        .filter(|&next_before| next_before < before)
+        .map_or(NextPage::Stuck, NextPage::Before)
+}
+
+/// Long fetches log a heartbeat every this many pages, so a slow backfill doesn't look hung.
+const FETCH_PROGRESS_LOG_PAGES: usize = 10;
+
+/// `fetch_complete_match_history` through `AppState::history_cache`. A cached history
+/// answers any fetch it covers: the rooms and `since`/`max_matches` bounds are applied
+/// to it. Fetches bounded any other way always go upstream, and only complete histories
+/// are stored. An expired history is brought up to date with `fetch_matches_since`, or
+/// fetched in full again if that refresh stops early.
+async fn fetch_match_history(
+    state: &AppState,
+    player_id: u64,
+    rule: &GameRule,
+    mode_ids: &[u64],
+    limits: &FetchLimits,
+) -> Result<FetchedHistory> {
+    let cacheable = limits.deadline.is_none()
+        && limits.max_pages.is_none()
+        && limits.until.is_none()
+        && !limits.include_incomplete;
+    if !cacheable {
+        return fetch_complete_match_history(&state.http_client, player_id, rule, mode_ids, limits)
+            .await;
+    }
+
+    let cached = state.history_cache.get(player_id, *rule);
+    let expired = match cached {
+        Some(_) => None,
+        None => state.history_cache.get_expired(player_id, *rule),
+    };
+
+    let (matches, parse_report, fetch_partial) = match (cached, expired) {
+        (Some((matches, parse_report)), _) => (matches, parse_report, false),
+        (None, Some((cached_matches, cached_report))) if !cached_matches.is_empty() => {
+            let newest_start = cached_matches[0].start_time;
+            let fetched_history =
+                fetch_matches_since(&state.http_client, player_id, rule, newest_start).await?;
+
+            if fetched_history.partial {
+                // Merging would leave a silent gap between the new games and the stored ones
+                warn!(
+                    player_id,
+                    "Incremental history refresh stopped early, fetching the history in full"
+                );
+                fetch_full_match_history(state, player_id, rule).await?
+            } else {
+                let matches = merge_match_histories(fetched_history.matches, cached_matches);
+                let parse_report = ParseReport {
+                    skipped_records: cached_report.skipped_records
+                        + fetched_history.parse_report.skipped_records,
+                    incomplete_games: cached_report.incomplete_games
+                        + fetched_history.parse_report.incomplete_games,
+                };
+                state
+                    .history_cache
+                    .insert(player_id, *rule, matches.clone(), parse_report.clone());
+                (matches, parse_report, false)
+            }
+        }
+        // A small or recent-only fetch is cheap, and not worth turning into a full one
+        _ if limits.since.is_some() || limits.max_matches.is_some() => {
+            return fetch_complete_match_history(
+                &state.http_client,
+                player_id,
+                rule,
+                mode_ids,
+                limits,
+            )
+            .await;
+        }
+        _ => fetch_full_match_history(state, player_id, rule).await?,
+    };
+
+    let mut matches = matches
+        .into_iter()
+        .filter(|game| mode_ids.contains(&game.game_type.mode_id()))
+        .filter(|game| limits.since.is_none_or(|since| game.start_time >= since))
+        .collect::<Vec<_>>();
+    let partial = fetch_partial
+        || limits
+            .max_matches
+            .is_some_and(|max_matches| matches.len() > max_matches);
+    if let Some(max_matches) = limits.max_matches {
+        matches.truncate(max_matches);
+    }
+
+    Ok(FetchedHistory {
+        matches,
+        partial,
+        parse_report,
+        pages: 0,
+    })
+}
+
+/// Every room's matches for `fetch_match_history`, as `(matches, parse_report, partial)`.
+/// Only a complete history is cached, since one cut short would be served as complete
+/// for the whole TTL.
+async fn fetch_full_match_history(
+    state: &AppState,
+    player_id: u64,
+    rule: &GameRule,
+) -> Result<(Vec<GameMatch>, ParseReport, bool)> {
+    let fetched_history = fetch_complete_match_history(
+        &state.http_client,
+        player_id,
+        rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &FetchLimits::default(),
+    )
+    .await?;
+    if !fetched_history.partial {
+        state.history_cache.insert(
+            player_id,
+            *rule,
+            fetched_history.matches.clone(),
+            fetched_history.parse_report.clone(),
+        );
+    }
+    Ok((
+        fetched_history.matches,
+        fetched_history.parse_report,
+        fetched_history.partial,
+    ))
+}
+
+/// Every room's matches started at or after `since`, newest first. Paging stops at
+/// `since`, so this costs one request for a player with few new games.
+async fn fetch_matches_since(
+    client: &reqwest::Client,
+    player_id: u64,
+    rule: &GameRule,
+    since: DateTime<Utc>,
+) -> Result<FetchedHistory> {
+    let limits = FetchLimits {
+        since: Some(since),
+        ..FetchLimits::default()
+    };
+    fetch_complete_match_history(
+        client,
+        player_id,
+        rule,
+        &rule.preset_mode_ids(ModePreset::All),
+        &limits,
+    )
+    .await
+}
+
+/// Puts newly fetched matches in front of an older history, both newest first. Games
+/// at the boundary are in both, so older copies of a start time already fetched anew
+/// are dropped.
+fn merge_match_histories(newer: Vec<GameMatch>, older: Vec<GameMatch>) -> Vec<GameMatch> {
+    let newer_starts = newer
+        .iter()
+        .map(|game| game.start_time)
+        .collect::<BTreeSet<_>>();
+
+    newer
+        .into_iter()
+        .chain(
+            older
+                .into_iter()
+                .filter(|game| !newer_starts.contains(&game.start_time)),
+        )
+        .collect()
+}
+
+async fn fetch_complete_match_history(
+    client: &reqwest::Client,
+    player_id: u64,
+    rule: &GameRule,
+    mode_ids: &[u64],
+    limits: &FetchLimits,
+) -> Result<FetchedHistory> {
+    let mode_list = mode_ids
+        .iter()
+        .map(|mode_id| mode_id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    // Small requests don't need full pages
+    let page_size = limits
+        .max_matches
+        .map_or(500, |max_matches| max_matches.min(500));
+
+    // Upstream only returns records inside the window, so a lower bound also ends paging early
+    let oldest_timestamp = limits
+        .since
+        .map_or(1262304000000, |since| since.timestamp_millis());
+
+    let mut current_timestamp = limits
+        .until
+        .map_or_else(|| chrono::Utc::now().timestamp(), |until| until.timestamp());
+    let mut all_matches = Vec::new();
+    let mut partial = false;
+    let mut parse_report = ParseReport::default();
+    let mut pages = 0;
+
+    loop {
+        let api_url = match_records_url(
+            rule,
+            player_id,
+            current_timestamp,
+            oldest_timestamp,
+            page_size,
+            &mode_list,
+        );
+
+        let response = get_json_with_retry(client, &api_url).await?;
+        pages += 1;
+
+        if all_matches.is_empty() {
+            probe_record_schema(&response)?;
+        }
+
+        let Some(records) = response.as_array() else {
+            warn!(response = %response, "Match records response is not an array");
+            return Err(UnexpectedUpstreamFormat.into());
+        };
+
+        all_matches.extend(parse_match_data(
+            records,
+            player_id,
+            limits.include_incomplete,
+            &mut parse_report,
+        ));
+
+        match next_page(records, page_size, current_timestamp) {
+            NextPage::Done => break,
+            NextPage::Before(before) => current_timestamp = before,
+            NextPage::Stuck => {
+                warn!(
+                    player_id,
+                    pages, "Full page of match records has no start time to continue from"
+                );
+                partial = true;
+                break;
+            }
+        }
+
+        if pages % FETCH_PROGRESS_LOG_PAGES == 0 {
+            info!(
+                player_id,
+                pages,
+                games = all_matches.len(),
+                "Still fetching match history"
+            );
+        }
+
+        if limits.max_pages.is_some_and(|max_pages| pages >= max_pages) {
+            partial = true;
+            break;
+        }
+
+        if let Some(max_matches) = limits.max_matches
+            && all_matches.len() >= max_matches
+        {
+            all_matches.truncate(max_matches);
+            partial = true;
+            break;
+        }
+
+        if limits
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            partial = true;
+            break;
+        }
+    }
+
+    if parse_report.skipped_records > 0 {
+        warn!(
+            player_id,
+            skipped_records = parse_report.skipped_records,
+            "Some match records could not be parsed"
+        );
+    }
+
+    Ok(FetchedHistory {
+        matches: all_matches,
+        partial,
+        parse_report,
+        pages,
+    })
+}
+
+/// Games without a plausible end time, which `parse_match_data` counts separately from
+/// malformed records.
+#[derive(Debug)]
+struct IncompleteGame;
+
+impl Display for IncompleteGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Game has no end time after its start")
+    }
+}
+
+impl std::error::Error for IncompleteGame {}
+
+/// Parses every record on its own, so a malformed one is logged and left out instead of
+/// failing the whole history.
+fn parse_match_data(
+    records: &[Value],
+    target_player_id: u64,
+    include_incomplete: bool,
+    parse_report: &mut ParseReport,
+) -> Vec<GameMatch> {
+    let mut incomplete_games = 0;
+    let matches = records
+        .iter()
+        .enumerate()
+        .filter_map(|(index, match_data)| {
+            match parse_single_match(match_data, target_player_id, include_incomplete) {
+                Ok(game) => Some(game),
+                Err(error) if error.is::<IncompleteGame>() => {
+                    incomplete_games += 1;
+                    None
+                }
+                Err(error) => {
+                    warn!(
+                        target_player_id,
+                        index,
+                        match_id = %match_data["_id"],
+                        start_time = %match_data["startTime"],
+                        "Skipping match record: {}",
+                        error
+                    );
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    parse_report.incomplete_games += incomplete_games;
+    parse_report.skipped_records += records.len() - matches.len() - incomplete_games;
+    matches
+}
+
+/// Fails with `IncompleteGame` for a game without an end time unless `include_incomplete`
+/// is set, and with a description of the problem for any other unusable record.
+fn parse_single_match(
+    match_data: &Value,
+    target_player_id: u64,
+    include_incomplete: bool,
+) -> Result<GameMatch> {
+    let mut player_data = match_data["players"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Record has no players list"))?
+        .iter()
+        .map(|player| {
+            let player_id = player["accountId"]
+                .as_u64()
+                .ok_or_else(|| anyhow!("Player has no accountId"))?;
+            let player_name = player["nickname"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Player {} has no nickname", player_id))?
+                .to_string();
+            let final_score = player["score"]
+                .as_i64()
+                .ok_or_else(|| anyhow!("Player {} has no score", player_id))?;
+            let pt_change = player["gradingScore"]
+                .as_i64()
+                .ok_or_else(|| anyhow!("Player {} has no gradingScore", player_id))?;
+            let level = player["level"]
+                .as_u64()
+                .and_then(PlayerLevel::from_level_id);
+            let placement = player["placement"].as_u64();
+            Ok((
+                player_id,
+                player_name,
+                final_score,
+                pt_change,
+                level,
+                placement,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // A repeated account would make the target's position, and so every stat,
+    // depend on which copy happens to come first
+    let mut account_ids = player_data
+        .iter()
+        .map(|player| player.0)
+        .collect::<Vec<_>>();
+    account_ids.sort_unstable();
+    if account_ids.windows(2).any(|pair| pair[0] == pair[1]) {
+        return Err(anyhow!("Record lists an account more than once"));
+    }
+
+    // The game's own placement already applies seat tiebreaks, so it wins when every
+    // player carries one. Otherwise rank by pt change, then final score (both descending).
+    // The game breaks remaining ties by seat, but records carry no seat and the players
+    // array isn't documented as seat order, so the account ID settles them instead: not
+    // the game's rule, but independent of how the record happens to list players.
+    let placement_source = if player_data.iter().all(|player| player.5.is_some()) {
+        player_data.sort_by_key(|player| player.5);
+        PlacementSource::Upstream
+    } else {
+        player_data.sort_by_key(|player| (Reverse(player.3), Reverse(player.2), player.0));
+        PlacementSource::GradingScore
+    };
+
+    let player_rank = player_data
+        .iter()
+        .position(|player| player.0 == target_player_id)
+        .map(|position| (position + 1) as u64)
+        .ok_or_else(|| anyhow!("Record doesn't list the target player"))?;
+
+    let raw_start_time = match_data["startTime"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("Record has no startTime"))?;
+    let raw_end_time = match_data["endTime"]
+        .as_u64()
+        .filter(|&end_time| end_time > raw_start_time);
+    if raw_end_time.is_none() && !include_incomplete {
+        return Err(IncompleteGame.into());
+    }
+
+    let start_time = DateTime::<Utc>::from_timestamp(raw_start_time as i64, 0)
+        .ok_or_else(|| anyhow!("startTime {} is out of range", raw_start_time))?;
+
+    let duration_minutes = raw_end_time.map_or(0, |end_time| (end_time - raw_start_time) / 60);
+
+    let mode_id = match_data["modeId"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("Record has no modeId"))?;
+    // Rooms added upstream after this build are skipped like any other unusable record
+    let game_type = GameType::try_from(mode_id)?;
+
+    // A seat count that doesn't fit the rule means the mode ID was mis-mapped, and
+    // keeping the match would feed e.g. 4th places into 3P stats
+    let seats = game_type.rule.player_count();
+    if player_data.len() as u64 != seats || player_rank > seats {
+        return Err(anyhow!(
+            "{} players don't fit mode {}",
+            player_data.len(),
+            mode_id
+        ));
+    }
+
+    let player_index = (player_rank - 1) as usize;
+    let pt_change = player_data[player_index].3;
+
+    let average_opponent_level = average_of(
+        player_data
+            .iter()
+            .filter(|player| player.0 != target_player_id)
+            .filter_map(|player| player.4)
+            .map(|level| level.scale()),
+    );
+
+    let own_score = player_data[player_index].2;
+    let score_margin = if player_index == 0 {
+        player_data
+            .get(1)
+            .map_or(0, |runner_up| own_score - runner_up.2)
+    } else {
+        player_data[player_index - 1].2 - own_score
+    };
+
+    let player_results = player_data
+        .into_iter()
+        .map(
+            |(account_id, name, final_score, _, level, _)| PlayerResult {
+                account_id,
+                name,
+                final_score,
+                level,
+                is_bot: false,
+            },
+        )
+        .collect();
+
+    Ok(GameMatch {
+        player_rank,
+        start_time,
+        duration_minutes,
+        game_type,
+        pt_change,
+        cumulative_pt: 0,
+        score_margin,
+        average_opponent_level,
+        player_results,
+        placement_source,
+    })
+}
+
+/// Applies the `?season=`, `?days=` and `?as_of=` windows. Season days follow the viewer's timezone,
+/// like the rest of the page.
+fn retain_history_window(
+    game_history: &mut Vec<GameMatch>,
+    season: Option<&Season>,
+    days: Option<u32>,
+    as_of: Option<NaiveDate>,
+    timezone: Tz,
+) {
+    if let Some(season) = season {
+        game_history
+            .retain(|game| season.contains(game.start_time.with_timezone(&timezone).date_naive()));
+    }
+
+    game_history.retain(|game| played_by(game, as_of, timezone));
+
+    // A window reaching back past the earliest representable date keeps every game
+    if let Some(cutoff) =
+        days.and_then(|days| Utc::now().checked_sub_signed(TimeDelta::days(days.into())))
+    {
+        game_history.retain(|game| game.start_time >= cutoff);
+    }
+}
+
+/// Whether the game was played on or before `as_of`, in the viewer's timezone.
+fn played_by(game: &GameMatch, as_of: Option<NaiveDate>, timezone: Tz) -> bool {
+    as_of.is_none_or(|as_of| game.start_time.with_timezone(&timezone).date_naive() <= as_of)
+}
+
+/// The nickname the player used in their most recent match, for a history sorted newest first.
+fn latest_nickname(game_history: &[GameMatch], player_id: u64) -> Option<String> {
+    game_history.iter().find_map(|game| {
+        game.player_results
+            .iter()
+            .find(|player| player.account_id == player_id)
+            .map(|player| player.name.clone())
+    })
+}
+
+/// Replaces every opponent's nickname with "Player 2", "Player 3", ... in table order.
+fn anonymize_opponents(game_history: &mut [GameMatch], player_id: u64) {
+    for game in game_history {
+        let opponents = game
+            .player_results
+            .iter_mut()
+            .filter(|player| player.account_id != player_id);
+
+        for (index, opponent) in opponents.enumerate() {
+            opponent.name = format!("Player {}", index + 2);
+        }
+    }
+}
+
+/// Fills in `cumulative_pt` for a history sorted newest first.
+fn annotate_cumulative_pt(game_history: &mut [GameMatch]) {
+    let mut running_total = 0;
+
+    for game in game_history.iter_mut().rev() {
+        running_total += game.pt_change;
+        game.cumulative_pt = running_total;
+    }
+}
+
+/// Net PT divided by total hours played, or `None` when no time has been played.
+fn pt_per_hour(game_history: &[GameMatch]) -> Option<f64> {
+    let total_pt: i64 = game_history.iter().map(|game| game.pt_change).sum();
+    let total_minutes: u64 = game_history.iter().map(|game| game.duration_minutes).sum();
+
+    if total_minutes == 0 {
+        None
+    } else {
+        Some(total_pt as f64 / (total_minutes as f64 / 60.0))
+    }
+}
+
+/// Headline figures for the page header.
+#[derive(Debug)]
+struct PlayerSummary {
+    games: usize,
+    /// `(rank, games, percentage)` for every rank the rule has
+    placements: Vec<(u64, u64, f64)>,
+    average_placement: f64,
+    total_pt: i64,
+    average_duration_minutes: f64,
+}
+
+/// `None` without any games, so nothing is divided by zero.
+fn summarize_player(game_history: &[GameMatch], rule: &GameRule) -> Option<PlayerSummary> {
+    if game_history.is_empty() {
+        return None;
+    }
+
+    let games = game_history.len();
+    let placements = count_placements(game_history, rule)
+        .into_iter()
+        .enumerate()
+        .map(|(index, count)| (index as u64 + 1, count, count as f64 / games as f64 * 100.0))
+        .collect();
+
+    Some(PlayerSummary {
+        games,
+        placements,
+        average_placement: game_history
+            .iter()
+            .map(|game| game.player_rank as f64)
+            .sum::<f64>()
+            / games as f64,
+        total_pt: game_history.iter().map(|game| game.pt_change).sum(),
+        average_duration_minutes: game_history
+            .iter()
+            .map(|game| game.duration_minutes as f64)
+            .sum::<f64>()
+            / games as f64,
+    })
+}
+
+/// Combined length of the counted games.
+#[derive(Debug)]
+struct TimePlayed {
+    total_minutes: u64,
+    games: usize,
+}
+
+impl TimePlayed {
+    fn hours(&self) -> u64 {
+        self.total_minutes / 60
+    }
+
+    fn minutes(&self) -> u64 {
+        self.total_minutes % 60
+    }
+
+    fn minutes_per_game(&self) -> f64 {
+        self.total_minutes as f64 / self.games as f64
+    }
+}
+
+/// `None` when no time has been played.
+fn time_played(game_history: &[GameMatch]) -> Option<TimePlayed> {
+    let total_minutes = game_history.iter().map(|game| game.duration_minutes).sum();
+
+    (total_minutes > 0).then_some(TimePlayed {
+        total_minutes,
+        games: game_history.len(),
+    })
+}
+
+fn length_weighted_averages(
+    game_history: &[GameMatch],
+    south_weight: f64,
+) -> Option<WeightedAverages> {
+    let (total_weight, placement_sum, pt_sum) = game_history.iter().fold(
+        (0.0, 0.0, 0.0),
+        |(total_weight, placement_sum, pt_sum), game| {
+            let weight = if game.game_type.category.is_east() {
+                1.0
+            } else {
+                south_weight
+            };
+            (
+                total_weight + weight,
+                placement_sum + weight * game.player_rank as f64,
+                pt_sum + weight * game.pt_change as f64,
+            )
+        },
+    );
+
+    if total_weight == 0.0 {
+        return None;
+    }
+
+    Some(WeightedAverages {
+        south_weight,
+        placement: placement_sum / total_weight,
+        pt_change: pt_sum / total_weight,
+    })
+}
+
+/// An unofficial rating replayed over a newest-first history, or `None` without games.
+fn estimate_rating(game_history: &[GameMatch], rule: &GameRule) -> Option<f64> {
+    if game_history.is_empty() {
+        return None;
+    }
+
+    let bonuses: &[f64] = match rule {
+        GameRule::ThreePlayer => &RATING_BONUSES_3P,
+        GameRule::FourPlayer => &RATING_BONUSES_4P,
+    };
+
+    let rating =
+        game_history
+            .iter()
+            .rev()
+            .enumerate()
+            .fold(RATING_START, |rating, (played, game)| {
+                let adjustment = if played < RATING_SETTLED_GAMES {
+                    1.0 - played as f64 * (1.0 - RATING_SETTLED_ADJUSTMENT)
+                        / RATING_SETTLED_GAMES as f64
+                } else {
+                    RATING_SETTLED_ADJUSTMENT
+                };
+                let bonus = bonuses[(game.player_rank - 1) as usize];
+
+                rating + adjustment * (bonus + (RATING_START - rating) / RATING_TABLE_DIVISOR)
+            });
+
+    Some(rating)
+}
+
+/// Mean absolute placement change between consecutive games; higher means streakier.
+fn placement_volatility(game_history: &[GameMatch]) -> Option<f64> {
+    average_of(
+        game_history
+            .windows(2)
+            .map(|pair| pair[0].player_rank.abs_diff(pair[1].player_rank) as f64),
+    )
+}
+
+fn summarize_pt_changes(game_history: &[GameMatch]) -> Option<PtChangeSummary> {
+    let mut total = (0, 0);
+    let mut gains = (0, 0);
+    let mut losses = (0, 0);
+
+    for game in game_history {
+        let pt_change = game.pt_change;
+        total = (total.0 + pt_change, total.1 + 1);
+        if pt_change > 0 {
+            gains = (gains.0 + pt_change, gains.1 + 1);
+        } else if pt_change < 0 {
+            losses = (losses.0 + pt_change, losses.1 + 1);
+        }
+    }
+
+    let mean = |(sum, count): (i64, i64)| (count > 0).then(|| sum as f64 / count as f64);
+
+    Some(PtChangeSummary {
+        net: total.0,
+        gross_gained: gains.0,
+        gross_lost: losses.0,
+        overall: mean(total)?,
+        gains: mean(gains),
+        losses: mean(losses),
+    })
+}
+
+fn count_score_outcomes(game_history: &[GameMatch]) -> ScoreOutcomeCounts {
+    let mut counts = ScoreOutcomeCounts::default();
+
+    for game in game_history {
+        let final_score = game.own_result().final_score;
+
+        if final_score > 0 {
+            counts.positive += 1;
+        } else if final_score < 0 {
+            counts.negative += 1;
+        }
+
+        if final_score.abs() <= ScoreOutcomeCounts::NEAR_ZERO_RANGE {
+            counts.near_zero += 1;
+        }
+    }
+
+    counts
+}
+
+/// The game type with the most matches; ties go to the one declared first in `GameCategory`.
+fn most_played_game_type(game_history: &[GameMatch]) -> Option<GameTypeShare> {
+    let mut counts = BTreeMap::new();
+    for game in game_history {
+        *counts.entry(game.game_type).or_insert(0) += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|&(game_type, games)| (games, Reverse(game_type)))
+        .map(|(game_type, games)| GameTypeShare {
+            game_type,
+            games,
+            percentage: games as f64 / game_history.len() as f64 * 100.0,
+        })
+}
+
+/// Games, average placement and 1st-place rate per game type, in `GameType` order.
+fn break_down_by_game_type(game_history: &[GameMatch]) -> Vec<GameTypeBreakdown> {
+    // (games, placement sum, placement square sum, first places, pt changes newest first)
+    // per game type, filled in one pass
+    let mut buckets = BTreeMap::<GameType, (u64, u64, u64, u64, Vec<i64>)>::new();
+    for game in game_history {
+        let bucket = buckets.entry(game.game_type).or_default();
+        bucket.0 += 1;
+        bucket.1 += game.player_rank;
+        bucket.2 += game.player_rank * game.player_rank;
+        bucket.3 += u64::from(game.player_rank == 1);
+        bucket.4.push(game.pt_change);
+    }
+
+    buckets
+        .into_iter()
+        .map(
+            |(
+                game_type,
+                (games, placement_sum, placement_square_sum, first_places, pt_changes),
+            )| {
+                let average_placement = placement_sum as f64 / games as f64;
+                let variance =
+                    placement_square_sum as f64 / games as f64 - average_placement.powi(2);
+
+                GameTypeBreakdown {
+                    game_type,
+                    games,
+                    average_placement,
+                    placement_spread: (games as usize >= LOW_SAMPLE_GAMES)
+                        .then(|| variance.max(0.0).sqrt()),
+                    first_place_rate: first_places as f64 / games as f64 * 100.0,
+                    cumulative_pt_path: cumulative_pt_sparkline_path(&pt_changes),
+                }
+            },
+        )
+        .collect()
+}
+
+/// `None` unless at least two rooms meet `GAME_TYPE_EXTREMES_MIN_GAMES`.
+fn find_game_type_extremes(breakdown: &[GameTypeBreakdown]) -> Option<GameTypeExtremes> {
+    let eligible = breakdown
+        .iter()
+        .filter(|entry| entry.games >= GAME_TYPE_EXTREMES_MIN_GAMES)
+        .collect::<Vec<_>>();
+    if eligible.len() < 2 {
+        return None;
+    }
+
+    let best = eligible
+        .iter()
+        .min_by(|a, b| a.average_placement.total_cmp(&b.average_placement))?;
+    let worst = eligible
+        .iter()
+        .max_by(|a, b| a.average_placement.total_cmp(&b.average_placement))?;
+
+    Some(GameTypeExtremes {
+        best: best.game_type,
+        best_average: best.average_placement,
+        worst: worst.game_type,
+        worst_average: worst.average_placement,
+    })
+}
+
+/// How often each placement was reached, next to the rate of a player placing at random.
+#[derive(Debug)]
+struct PlacementShare {
+    rank: u64,
+    games: u64,
+    /// `None` without any games
+    percentage: Option<f64>,
+    neutral_percentage: f64,
+}
+
+impl PlacementShare {
+    /// Percentage points above (positive) or below the neutral rate.
+    fn delta(&self) -> Option<f64> {
+        self.percentage
+            .map(|percentage| percentage - self.neutral_percentage)
+    }
+}
+
+fn placement_shares(game_history: &[GameMatch], rule: &GameRule) -> Vec<PlacementShare> {
+    let games = game_history.len();
+    let neutral_percentage = 100.0 / rule.player_count() as f64;
+
+    count_placements(game_history, rule)
+        .into_iter()
+        .enumerate()
+        .map(|(index, count)| PlacementShare {
+            rank: index as u64 + 1,
+            games: count,
+            percentage: (games > 0).then(|| count as f64 / games as f64 * 100.0),
+            neutral_percentage,
+        })
+        .collect()
+}
+
+/// Average final score and PT for one placement, showing how big wins and losses run.
+#[derive(Debug)]
+struct PlacementAverages {
+    rank: u64,
+    games: u64,
+    average_final_score: f64,
+    average_pt_change: f64,
+}
+
+/// Placements never reached are left out.
+fn average_by_placement(game_history: &[GameMatch], rule: &GameRule) -> Vec<PlacementAverages> {
+    let mut totals = vec![(0, 0, 0); rule.player_count() as usize];
+    for game in game_history {
+        let (games, final_score, pt_change) = &mut totals[(game.player_rank - 1) as usize];
+        *games += 1;
+        *final_score += game.own_result().final_score;
+        *pt_change += game.pt_change;
+    }
+
+    totals
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (games, _, _))| *games > 0)
+        .map(
+            |(index, (games, final_score, pt_change))| PlacementAverages {
+                rank: index as u64 + 1,
+                games,
+                average_final_score: final_score as f64 / games as f64,
+                average_pt_change: pt_change as f64 / games as f64,
+            },
+        )
+        .collect()
+}
+
+/// Number of games finished in each placement, indexed by rank - 1.
+fn count_placements(game_history: &[GameMatch], rule: &GameRule) -> Vec<u64> {
+    let mut counts = vec![0; rule.player_count() as usize];
+    for game in game_history {
+        counts[(game.player_rank - 1) as usize] += 1;
+    }
+    counts
+}
+
+/// Walks back from the newest game of a newest-first history until each condition breaks.
+fn find_current_streak(game_history: &[GameMatch], rule: &GameRule) -> CurrentStreak {
+    let top_half_rank = rule.player_count() / 2;
+    let streak_length = |condition: &dyn Fn(&GameMatch) -> bool| {
+        game_history
+            .iter()
+            .take_while(|game| condition(game))
+            .count()
+    };
+
+    CurrentStreak {
+        top_half: streak_length(&|game| game.player_rank <= top_half_rank),
+        last_place: streak_length(&|game| game.player_rank == rule.player_count()),
+    }
+}
+
+/// Tallies every maximal run of top-half and of last-place finishes in one pass.
+fn count_streaks(game_history: &[GameMatch], rule: &GameRule) -> StreakDistribution {
+    let top_half_rank = rule.player_count() / 2;
+    let mut distribution = StreakDistribution {
+        top_half: BTreeMap::new(),
+        last_place: BTreeMap::new(),
+    };
+
+    let record = |runs: &mut BTreeMap<usize, usize>, length: usize| {
+        if length >= 2 {
+            *runs.entry(length).or_default() += 1;
+        }
+    };
+
+    let (mut top_half_run, mut last_place_run) = (0, 0);
+    for game in game_history {
+        if game.player_rank <= top_half_rank {
+            top_half_run += 1;
+        } else {
+            record(&mut distribution.top_half, top_half_run);
+            top_half_run = 0;
+        }
+
+        if game.player_rank == rule.player_count() {
+            last_place_run += 1;
+        } else {
+            record(&mut distribution.last_place, last_place_run);
+            last_place_run = 0;
+        }
+    }
+    record(&mut distribution.top_half, top_half_run);
+    record(&mut distribution.last_place, last_place_run);
+
+    distribution
+}
+
+fn find_duration_extremes(game_history: &[GameMatch]) -> Option<DurationExtremes> {
+    let longest = game_history
+        .iter()
+        .max_by_key(|game| game.duration_minutes)?;
+    let shortest = game_history
+        .iter()
+        .min_by_key(|game| game.duration_minutes)?;
+
+    Some(DurationExtremes {
+        longest: longest.into(),
+        shortest: shortest.into(),
+    })
+}
+
+/// Scans a newest-first history for the longest break; `None` with fewer than two games.
+fn find_longest_gap(game_history: &[GameMatch]) -> Option<LongestGap> {
+    game_history
+        .windows(2)
+        .max_by_key(|pair| pair[0].start_time - pair[1].start_time)
+        .map(|pair| LongestGap {
+            last_game_before: pair[1].start_time,
+            first_game_after: pair[0].start_time,
+        })
+}
+
+const SPARKLINE_GAMES: usize = 20;
+const SPARKLINE_WIDTH: f64 = 100.0;
+const SPARKLINE_HEIGHT: f64 = 20.0;
+
+/// SVG path data for the most recent placements, oldest on the left and 1st at the top.
+/// `None` when there are fewer than two games to draw a line between.
+fn placement_sparkline_path(game_history: &[GameMatch], rule: &GameRule) -> Option<String> {
+    let recent_games = &game_history[..game_history.len().min(SPARKLINE_GAMES)];
+    if recent_games.len() < 2 {
+        return None;
+    }
+
+    let x_step = SPARKLINE_WIDTH / (recent_games.len() - 1) as f64;
+    let y_step = SPARKLINE_HEIGHT / (rule.player_count() - 1) as f64;
+
+    let points = recent_games
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, game)| {
+            let x = index as f64 * x_step;
+            let y = (game.player_rank - 1) as f64 * y_step;
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect::<Vec<_>>();
+
+    Some(format!("M{}", points.join(" L")))
+}
+
+/// SVG path data for the running total of `pt_changes` (newest first), drawn oldest on
+/// the left and starting from zero, so even a single game gives a line. A total that
+/// never changes is drawn flat across the middle.
+fn cumulative_pt_sparkline_path(pt_changes: &[i64]) -> String {
+    let totals = std::iter::once(0)
+        .chain(pt_changes.iter().rev().scan(0, |total, pt_change| {
+            *total += pt_change;
+            Some(*total)
+        }))
+        .collect::<Vec<_>>();
+
+    let highest = totals.iter().copied().max().unwrap_or(0);
+    let lowest = totals.iter().copied().min().unwrap_or(0);
+    let range = (highest - lowest) as f64;
+    let x_step = SPARKLINE_WIDTH / (totals.len() - 1).max(1) as f64;
+
+    let points = totals
+        .iter()
+        .enumerate()
+        .map(|(index, &total)| {
+            let x = index as f64 * x_step;
+            let y = if range == 0.0 {
+                SPARKLINE_HEIGHT / 2.0
+            } else {
+                (highest - total) as f64 / range * SPARKLINE_HEIGHT
+            };
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect::<Vec<_>>();
+
+    format!("M{}", points.join(" L"))
+}
+
+const TREND_WIDTH: f64 = 300.0;
+const TREND_HEIGHT: f64 = 60.0;
+
+/// Average placement per calendar month, charted 1st-at-the-top like the sparkline.
+#[derive(Debug)]
+struct MonthlyTrend {
+    path: String,
+    points: Vec<MonthlyPoint>,
+}
+
+#[derive(Debug)]
+struct MonthlyPoint {
+    /// `YYYY-MM` in the viewer's timezone
+    month: String,
+    games: usize,
+    average_placement: f64,
+    low_sample: bool,
+    x: f64,
+    y: f64,
+}
+
+/// `None` unless games were played in at least two months. Months without games are
+/// skipped, so the line connects straight through them.
+fn monthly_placement_trend(
+    game_history: &[GameMatch],
+    rule: &GameRule,
+    timezone: Tz,
+) -> Option<MonthlyTrend> {
+    // (games, placement sum) per (year, month), oldest first
+    let mut buckets = BTreeMap::<(i32, u32), (usize, u64)>::new();
+    for game in game_history {
+        let local_time = game.start_time.with_timezone(&timezone);
+        let bucket = buckets
+            .entry((local_time.year(), local_time.month()))
+            .or_default();
+        bucket.0 += 1;
+        bucket.1 += game.player_rank;
+    }
+
+    if buckets.len() < 2 {
+        return None;
+    }
+
+    let x_step = TREND_WIDTH / (buckets.len() - 1) as f64;
+    let y_step = TREND_HEIGHT / (rule.player_count() - 1) as f64;
+
+    let points = buckets
+        .into_iter()
+        .enumerate()
+        .map(|(index, ((year, month), (games, placement_sum)))| {
+            let average_placement = placement_sum as f64 / games as f64;
+            MonthlyPoint {
+                month: format!("{:04}-{:02}", year, month),
+                games,
+                average_placement,
+                low_sample: games < LOW_SAMPLE_GAMES,
+                x: index as f64 * x_step,
+                y: (average_placement - 1.0) * y_step,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let path = points
+        .iter()
+        .map(|point| format!("{:.1},{:.1}", point.x, point.y))
+        .collect::<Vec<_>>()
+        .join(" L");
+
+    Some(MonthlyTrend {
+        path: format!("M{}", path),
+        points,
+    })
+}
+
+fn average_of(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f64)
+    }
+}
+
+fn build_play_time_heatmap(game_history: &[GameMatch], timezone: Tz) -> PlayTimeHeatmap {
+    let mut cells = vec![vec![HeatmapCell::default(); 24]; 7];
+
+    for game in game_history {
+        let local_start = game.start_time.with_timezone(&timezone);
+        let day = local_start.weekday().num_days_from_monday() as usize;
+        let hour = local_start.hour() as usize;
+
+        let cell = &mut cells[day][hour];
+        cell.games += 1;
+        cell.placement_sum += game.player_rank;
+    }
+
+    let max_games = cells
+        .iter()
+        .flatten()
+        .map(|cell| cell.games)
+        .max()
+        .unwrap_or(0);
+
+    let rows = cells
+        .into_iter()
+        .enumerate()
+        .map(|(day, cells)| HeatmapRow {
+            weekday: Weekday::try_from(day as u8).unwrap(),
+            cells,
+        })
+        .collect();
+
+    PlayTimeHeatmap { rows, max_games }
+}
+
+#[derive(Clone)]
+struct BasicAuthCredentials {
+    user: String,
+    password: String,
+}
+
+async fn require_basic_auth(
+    State(credentials): State<BasicAuthCredentials>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64_STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .is_some_and(|decoded| {
+            decoded.split_once(':').is_some_and(|(user, password)| {
+                // Constant-time, and both halves always, so timing doesn't reveal how
+                // much of a guess was right
+                let user_matches = user.as_bytes().ct_eq(credentials.user.as_bytes());
+                let password_matches = password.as_bytes().ct_eq(credentials.password.as_bytes());
+                (user_matches & password_matches).into()
+            })
+        });
+
+    if authorized {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"majsoul-stats\"")],
+        )
+            .into_response()
+    }
+}
+
+/// Liveness: answers as long as the process is serving at all.
+async fn handle_health() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness: `503` until `main` has finished setting up.
+async fn handle_ready(State(state): State<AppState>) -> StatusCode {
+    if state.ready.load(Ordering::Acquire) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Rejects request targets (path plus query) longer than the configured number of bytes.
+async fn limit_uri_length(
+    State(max_uri_bytes): State<usize>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let target_length = request
+        .uri()
+        .path_and_query()
+        .map_or(0, |target| target.as_str().len());
+
+    if target_length > max_uri_bytes {
+        StatusCode::URI_TOO_LONG.into_response()
+    } else {
+        next.run(request).await
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // Parsed first, so `--help` and a malformed address exit before any setup
+    let cli = Cli::parse();
+
+    let json_logs = match std::env::var("LOG_FORMAT") {
+        Ok(format) => match format.as_str() {
+            "json" => true,
+            "pretty" => false,
+            _ => panic!("LOG_FORMAT must be json or pretty, got: {}", format),
+        },
+        // Readable for someone watching a terminal, structured for a log pipeline
+        Err(_) => !std::io::stdout().is_terminal(),
+    };
+    let subscriber = tracing_subscriber::fmt().with_env_filter(
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    );
+    if json_logs {
+        subscriber.json().init();
+    } else {
+        subscriber.pretty().init();
+    }
+
+    check_game_modes();
+
+    let user_agent = std::env::var("MAJSOUL_STATS_USER_AGENT")
+        .unwrap_or_else(|_| DEFAULT_USER_AGENT.to_string());
+
+    let pool_max_idle_per_host = std::env::var("MAJSOUL_STATS_POOL_MAX_IDLE_PER_HOST")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_POOL_MAX_IDLE_PER_HOST must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_POOL_MAX_IDLE_PER_HOST);
+
+    let pool_idle_timeout_secs = std::env::var("MAJSOUL_STATS_POOL_IDLE_TIMEOUT_SECS")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_POOL_IDLE_TIMEOUT_SECS must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_POOL_IDLE_TIMEOUT_SECS);
+
+    let min_tls = std::env::var("MAJSOUL_STATS_MIN_TLS").ok();
+    let min_tls_version = min_tls.as_deref().map(|version| match version {
+        "1.0" => reqwest::tls::Version::TLS_1_0,
+        "1.1" => reqwest::tls::Version::TLS_1_1,
+        "1.2" => reqwest::tls::Version::TLS_1_2,
+        // The native TLS backend can't require 1.3, though it still negotiates it
+        _ => panic!(
+            "MAJSOUL_STATS_MIN_TLS must be one of 1.0, 1.1 or 1.2, got: {}",
+            version
+        ),
+    });
+
+    // reqwest routes requests through HTTPS_PROXY, HTTP_PROXY or ALL_PROXY (and their
+    // lowercase forms) when set, skipping hosts listed in NO_PROXY
+    let mut http_client = reqwest::Client::builder()
+        .user_agent(user_agent.clone())
+        .pool_max_idle_per_host(pool_max_idle_per_host)
+        .pool_idle_timeout(Duration::from_secs(pool_idle_timeout_secs));
+    if let Some(version) = min_tls_version {
+        http_client = http_client.min_tls_version(version);
+    }
+    let http_client = http_client.build().unwrap();
+
+    let max_rendered_matches = std::env::var("MAJSOUL_STATS_MAX_RENDERED_MATCHES")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_MAX_RENDERED_MATCHES must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_MAX_RENDERED_MATCHES);
+
+    let default_timezone = match std::env::var("DEFAULT_TZ") {
+        Ok(name) => name
+            .parse::<Tz>()
+            .unwrap_or_else(|_| panic!("DEFAULT_TZ is not a valid IANA timezone: {}", name)),
+        Err(_) => Tz::UTC,
+    };
+
+    let seasons = match std::env::var("MAJSOUL_STATS_SEASONS") {
+        Ok(config) => parse_seasons(&config)
+            .unwrap_or_else(|error| panic!("MAJSOUL_STATS_SEASONS is malformed: {}", error)),
+        Err(_) => Vec::new(),
+    };
+
+    let slow_request_ms = std::env::var("MAJSOUL_STATS_SLOW_REQUEST_MS")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_SLOW_REQUEST_MS must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_SLOW_REQUEST_MS);
+
+    let player_id_cache_ttl_secs = std::env::var("MAJSOUL_STATS_PLAYER_ID_CACHE_TTL_SECS")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_PLAYER_ID_CACHE_TTL_SECS must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_PLAYER_ID_CACHE_TTL_SECS);
+
+    let history_cache_ttl_secs = std::env::var("MAJSOUL_STATS_HISTORY_CACHE_TTL_SECS")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_HISTORY_CACHE_TTL_SECS must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_HISTORY_CACHE_TTL_SECS);
+
+    let instance_notice = std::env::var("INSTANCE_NOTICE")
+        .ok()
+        .filter(|notice| !notice.trim().is_empty())
+        .map(Arc::from);
+
+    let placement_points_for =
+        |variable: &str, rule: GameRule, default: &[i64]| match std::env::var(variable) {
+            Ok(config) => parse_placement_points(&config, rule)
+                .unwrap_or_else(|error| panic!("{} is malformed: {}", variable, error)),
+            Err(_) => default.to_vec(),
+        };
+    let placement_points = PlacementPoints {
+        three_player: placement_points_for(
+            "MAJSOUL_STATS_PLACEMENT_POINTS_3P",
+            GameRule::ThreePlayer,
+            &DEFAULT_PLACEMENT_POINTS_3P,
+        ),
+        four_player: placement_points_for(
+            "MAJSOUL_STATS_PLACEMENT_POINTS_4P",
+            GameRule::FourPlayer,
+            &DEFAULT_PLACEMENT_POINTS_4P,
+        ),
+    };
+
+    let bot_accounts = BotAccounts {
+        ids: std::env::var("BOT_IDS")
+            .map(|ids| {
+                ids.split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(|id| {
+                        id.parse()
+                            .unwrap_or_else(|_| panic!("BOT_IDS contains an invalid ID: {}", id))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        names: std::env::var("BOT_NAMES")
+            .map(|names| {
+                names
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    // `DISPLAY_ALIASES=12345678=Alice,23456789=Bob`
+    let display_aliases = DisplayAliases(
+        std::env::var("DISPLAY_ALIASES")
+            .map(|aliases| {
+                aliases
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|alias| !alias.is_empty())
+                    .map(|alias| {
+                        alias
+                            .split_once('=')
+                            .and_then(|(id, label)| {
+                                Some((id.trim().parse().ok()?, label.trim().to_string()))
+                            })
+                            .unwrap_or_else(|| {
+                                panic!("DISPLAY_ALIASES contains an invalid entry: {}", alias)
+                            })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    );
+
+    let default_fetch_scope = match std::env::var("DEFAULT_FETCH_SCOPE") {
+        Ok(scope) => match scope.as_str() {
+            "all" => FetchScope::All,
+            "recent" => FetchScope::Recent,
+            _ => panic!("DEFAULT_FETCH_SCOPE must be all or recent, got: {}", scope),
+        },
+        Err(_) => FetchScope::default(),
+    };
+
+    let state = AppState {
+        http_client,
+        max_rendered_matches,
+        default_timezone,
+        seasons: Arc::new(seasons),
+        slow_request_threshold: Duration::from_millis(slow_request_ms),
+        instance_notice,
+        placement_points: Arc::new(placement_points),
+        bot_accounts: Arc::new(bot_accounts),
+        display_aliases: Arc::new(display_aliases),
+        default_fetch_scope,
+        player_id_cache: Arc::new(PlayerIdCache::new(Duration::from_secs(
+            player_id_cache_ttl_secs,
+        ))),
+        history_cache: Arc::new(HistoryCache::new(Duration::from_secs(
+            history_cache_ttl_secs,
+        ))),
+        ready: Arc::new(AtomicBool::new(false)),
+    };
+
+    let mut app = Router::new()
+        .route("/search/3p/{name}", get(handle_3p_player_stats))
+        .route("/search/4p/{name}", get(handle_4p_player_stats))
+        .route("/search/3p/id/{id}", get(handle_3p_player_stats_by_id))
+        .route("/search/4p/id/{id}", get(handle_4p_player_stats_by_id))
+        .route("/today/3p/{name}", get(handle_3p_today))
+        .route("/today/4p/{name}", get(handle_4p_today))
+        .route("/custom/3p/{name}", get(handle_3p_custom_modes))
+        .route("/custom/4p/{name}", get(handle_4p_custom_modes))
+        .route("/p/3p/{lookup}", get(handle_3p_player_lookup))
+        .route("/p/4p/{lookup}", get(handle_4p_player_lookup))
+        .route("/export/3p/{name}/matches.csv", get(handle_3p_csv_export))
+        .route("/export/4p/{name}/matches.csv", get(handle_4p_csv_export))
+        .route(
+            "/export/3p/{name}/summary.json",
+            get(handle_3p_summary_export),
+        )
+        .route(
+            "/export/4p/{name}/summary.json",
+            get(handle_4p_summary_export),
+        )
+        .route("/card/3p/{file_name}", get(handle_3p_stat_card))
+        .route("/card/4p/{file_name}", get(handle_4p_stat_card))
+        .route("/embed/3p/{name}", get(handle_3p_embed))
+        .route("/embed/4p/{name}", get(handle_4p_embed))
+        .route("/self-compare/3p/{name}", get(handle_3p_self_compare))
+        .route("/self-compare/4p/{name}", get(handle_4p_self_compare))
+        .route("/api/modes", get(handle_supported_modes))
+        .route("/api/placements/3p/{name}", get(handle_3p_placements))
+        .route("/api/placements/4p/{name}", get(handle_4p_placements))
+        .route("/api/3p/{name}", get(handle_3p_player_api))
+        .route("/api/4p/{name}", get(handle_4p_player_api))
+        .route("/api/recent/3p/{name}", get(handle_3p_recent_matches))
+        .route("/api/recent/4p/{name}", get(handle_4p_recent_matches))
+        .route("/opponents/3p/{name}", get(handle_3p_opponents))
+        .route("/opponents/4p/{name}", get(handle_4p_opponents))
+        .route("/api/team/3p", post(handle_3p_team))
+        .route("/api/team/4p", post(handle_4p_team));
+
+    let max_concurrent_requests = std::env::var("MAJSOUL_STATS_MAX_CONCURRENT_REQUESTS")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .ok()
+                .filter(|&limit: &usize| limit > 0)
+                .expect("MAJSOUL_STATS_MAX_CONCURRENT_REQUESTS must be a positive integer")
+        })
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+    let max_body_bytes = std::env::var("MAJSOUL_STATS_MAX_BODY_BYTES")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_MAX_BODY_BYTES must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_MAX_BODY_BYTES);
+
+    let max_uri_bytes = std::env::var("MAJSOUL_STATS_MAX_URI_BYTES")
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .expect("MAJSOUL_STATS_MAX_URI_BYTES must be a non-negative integer")
+        })
+        .unwrap_or(DEFAULT_MAX_URI_BYTES);
+
+    let basic_auth_enabled =
+        std::env::var("AUTH_USER").is_ok() && std::env::var("AUTH_PASS").is_ok();
+    let tls_enabled = std::env::var("TLS_CERT").is_ok() && std::env::var("TLS_KEY").is_ok();
+
+    // Debug routes expose raw pagination and configuration, and stay off unless asked for
+    if std::env::var("MAJSOUL_STATS_DEBUG_ROUTES").is_ok_and(|value| value == "1") {
+        // Credentials are reported as enabled or not, never by value
+        let effective_config = Json(json!({
+            "upstream": {
+                "3p": GameRule::ThreePlayer.api_base_url(),
+                "4p": GameRule::FourPlayer.api_base_url(),
+            },
+            "user_agent": user_agent,
+            "pool_max_idle_per_host": pool_max_idle_per_host,
+            "pool_idle_timeout_secs": pool_idle_timeout_secs,
+            "min_tls": min_tls,
+            "bind": cli.bind.to_string(),
+            "max_rendered_matches": state.max_rendered_matches,
+            "default_timezone": state.default_timezone.name(),
+            "seasons": state
+                .seasons
+                .iter()
+                .map(|season| season.name.as_str())
+                .collect::<Vec<_>>(),
+            "slow_request_ms": state.slow_request_threshold.as_millis(),
+            "player_id_cache_ttl_secs": state.player_id_cache.ttl.as_secs(),
+            "history_cache_ttl_secs": state.history_cache.ttl.as_secs(),
+            "instance_notice": state.instance_notice.as_deref(),
+            "placement_points": {
+                "3p": state.placement_points.three_player,
+                "4p": state.placement_points.four_player,
+            },
+            "default_fetch_scope": match state.default_fetch_scope {
+                FetchScope::All => "all",
+                FetchScope::Recent => "recent",
+            },
+            "max_concurrent_requests": max_concurrent_requests,
+            "max_body_bytes": max_body_bytes,
+            "max_uri_bytes": max_uri_bytes,
+            "basic_auth": basic_auth_enabled,
+            "tls": tls_enabled,
+        }));
+
+        app = app
+            .route("/debug/page/3p/{name}", get(handle_3p_debug_page))
+            .route("/debug/page/4p/{name}", get(handle_4p_debug_page))
+            .route(
+                "/debug/config",
+                get(move || std::future::ready(effective_config.clone())),
+            );
+    }
+
+    // Bounds total work across all clients and routes, which share one semaphore; rejected
+    // credentials never take a slot
+    app = app.layer(GlobalConcurrencyLimitLayer::new(max_concurrent_requests));
+
+    // Authentication is only enabled when both variables are set
+    if let (Ok(user), Ok(password)) = (std::env::var("AUTH_USER"), std::env::var("AUTH_PASS")) {
+        let credentials = BasicAuthCredentials { user, password };
+        app = app.layer(middleware::from_fn_with_state(
+            credentials,
+            require_basic_auth,
+        ));
+    }
+
+    // Probes are added after the limits and authentication, so orchestrators reach them
+    // without credentials and even when every request slot is taken
+    let ready = state.ready.clone();
+    let app = app
+        .route("/health", get(handle_health))
+        .route("/ready", get(handle_ready))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(middleware::from_fn_with_state(
+            max_uri_bytes,
+            limit_uri_length,
+        ))
+        // Every log line of a request, down to upstream fetch warnings, carries its ID
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request| {
+                let request_id = request
+                    .extensions()
+                    .get::<RequestId>()
+                    .and_then(|id| id.header_value().to_str().ok())
+                    .unwrap_or_default();
+                info_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    request_id,
+                )
+            }),
+        )
+        // Layers run outermost-last: the ID is assigned (unless the client sent one)
+        // before it is traced and copied onto the response
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state);
+
+    // HTTPS is only served when both variables are set; the files are checked before binding
+    if let (Ok(cert_path), Ok(key_path)) = (std::env::var("TLS_CERT"), std::env::var("TLS_KEY")) {
+        let tls_config = RustlsConfig::from_pem_file(&cert_path, &key_path)
+            .await
+            .unwrap_or_else(|error| {
+                panic!(
+                    "Failed to load TLS_CERT {} and TLS_KEY {}: {}",
+                    cert_path, key_path, error
+                )
+            });
+
+        ready.store(true, Ordering::Release);
+        axum_server::bind_rustls(cli.bind, tls_config)
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+        return;
+    }
+
+    let listener = tokio::net::TcpListener::bind(cli.bind)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to listen on {}: {}", cli.bind, error));
+    ready.store(true, Ordering::Release);
+    axum::serve(listener, app).await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_game_type_round_trips_through_its_mode_id() {
+        use GameCategory::*;
+
+        for rule in [GameRule::ThreePlayer, GameRule::FourPlayer] {
+            for category in [Gold, GoldEast, Jade, JadeEast, Throne, ThroneEast] {
+                let game_type = GameType { rule, category };
+                let mode_id = game_type.mode_id();
+                assert_eq!(GameType::try_from(mode_id).unwrap(), game_type);
+                assert!(rule.mode_ids().any(|id| id == mode_id));
+                assert!(rule.other().mode_ids().all(|id| id != mode_id));
+            }
+        }
+    }
+
+    #[test]
+    fn every_mode_id_round_trips_through_its_game_type() {
+        check_game_modes();
+        for &(mode_id, rule, category) in GAME_MODES {
+            let game_type = GameType::try_from(mode_id).unwrap();
+            assert_eq!((game_type.rule, game_type.category), (rule, category));
+            assert_eq!(game_type.mode_id(), mode_id);
+        }
+        assert!(GameType::try_from(40).is_err());
+    }
+
+    /// A finished record in the shape `player_records` returns; each player is
+    /// `(accountId, score, gradingScore)`.
+    fn record(mode_id: u64, players: &[(u64, i64, i64)]) -> Value {
+        json!({
+            "_id": "test",
+            "modeId": mode_id,
+            "startTime": 1_700_000_000,
+            "endTime": 1_700_001_800,
+            "players": players
+                .iter()
+                .map(|&(account_id, score, grading_score)| json!({
+                    "accountId": account_id,
+                    "nickname": format!("player{}", account_id),
+                    "level": 10301,
+                    "score": score,
+                    "gradingScore": grading_score,
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// A response under `tests/fixtures/`, recorded from `player_records` with names and
+    /// IDs anonymized.
+    fn load_fixture(name: &str) -> Value {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        let contents = std::fs::read_to_string(&path)
+            .unwrap_or_else(|error| panic!("reading {}: {}", path.display(), error));
+        serde_json::from_str(&contents).unwrap()
+    }
+
+    /// A fixture page's records, newest first.
+    fn fixture_records(name: &str) -> Vec<Value> {
+        load_fixture(name).as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn parses_a_4p_page() {
+        let mut report = ParseReport::default();
+        let matches = parse_match_data(
+            &fixture_records("records_4p.json"),
+            1001,
+            false,
+            &mut report,
+        );
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(report.skipped_records, 0);
+        assert_eq!(report.incomplete_games, 0);
+
+        let latest = &matches[0];
+        assert_eq!(latest.player_rank, 1);
+        assert_eq!(latest.pt_change, 85);
+        assert_eq!(latest.duration_minutes, 25);
+        assert_eq!(latest.score_margin, 10_600);
+        assert_eq!(latest.game_type.rule, GameRule::FourPlayer);
+        assert_eq!(latest.game_type.category, GameCategory::Jade);
+
+        let older = &matches[1];
+        assert_eq!(older.player_rank, 3);
+        assert_eq!(older.pt_change, -10);
+        assert_eq!(older.score_margin, 6_000);
+        assert_eq!(older.game_type.category, GameCategory::Gold);
+        assert_eq!(older.own_result().name, "alpha_old");
+    }
+
+    #[test]
+    fn skips_records_from_unknown_modes() {
+        let mut report = ParseReport::default();
+        let matches = parse_match_data(
+            &fixture_records("records_unknown_mode.json"),
+            1001,
+            false,
+            &mut report,
+        );
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].game_type.category, GameCategory::Jade);
+        assert_eq!(report.skipped_records, 1);
+    }
+
+    #[test]
+    fn parses_a_3p_page_and_counts_incomplete_games() {
+        let mut report = ParseReport::default();
+        let records = fixture_records("records_3p.json");
+        let matches = parse_match_data(&records, 2001, false, &mut report);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].player_rank, 2);
+        assert_eq!(matches[0].game_type.rule, GameRule::ThreePlayer);
+        assert_eq!(report.incomplete_games, 1);
+        assert_eq!(report.skipped_records, 0);
+
+        let mut report = ParseReport::default();
+        let matches = parse_match_data(&records, 2001, true, &mut report);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].duration_minutes, 0);
+        assert_eq!(report.incomplete_games, 0);
+    }
+
+    fn rank_of(record: &Value, player_id: u64) -> u64 {
+        parse_single_match(record, player_id, false)
+            .unwrap()
+            .player_rank
+    }
+
+    #[test]
+    fn empty_search_is_not_found_but_unexpected_shapes_are_bad_gateway() {
+        let status_for = |response: Value| {
+            search_error_status(&parse_search_response(&response, "someone").unwrap_err())
+        };
+        assert_eq!(status_for(json!([])), StatusCode::NOT_FOUND);
+        assert_eq!(
+            status_for(json!({"error": "internal"})),
+            StatusCode::BAD_GATEWAY
+        );
+        assert_eq!(status_for(json!({"players": []})), StatusCode::BAD_GATEWAY);
+        assert_eq!(status_for(json!("maintenance")), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn ambiguous_names_answer_with_their_candidates() {
+        let error = anyhow::Error::new(AmbiguousPlayerName(vec![
+            (111, "dup".to_string()),
+            (222, "dup".to_string()),
+        ]));
+        assert_eq!(search_error_status(&error), StatusCode::CONFLICT);
+
+        let response = search_error_json(&error, GameRule::FourPlayer).into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice::<Value>(&body).unwrap();
+        assert_eq!(body["candidates"][1]["player_id"], 222);
+        assert_eq!(body["candidates"][1]["url"], "/search/4p/id/222");
+    }
+
+    #[test]
+    fn search_skips_results_without_an_id() {
+        let response = json!([
+            {"nickname": "someone"},
+            {"id": 42, "nickname": "someone"},
+        ]);
+        assert_eq!(
+            parse_search_response(&response, "someone").unwrap(),
+            vec![(42, "someone".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn html_pages_declare_utf8_in_header_and_markup() {
+        let response = HtmlTemplate(OtherRuleTemplate {
+            language: Language::Ja,
+            instance_notice: None,
+            player_name: "雀士".to_string(),
+            rule: GameRule::FourPlayer,
+            other_rule: GameRule::ThreePlayer,
+        })
+        .into_response();
+
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let html = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(html.to_vec()).unwrap();
+        assert!(html.starts_with("<meta charset=\"utf-8\" />"));
+        assert!(html.contains("雀士"));
+    }
+
+    #[test]
+    fn search_with_no_results_is_an_error() {
+        let error = parse_search_response(&json!([]), "nobody").unwrap_err();
+        assert!(error.to_string().contains("nobody"));
+    }
+
+    #[test]
+    fn search_error_object_keeps_the_upstream_message() {
+        let error =
+            parse_search_response(&json!({"error": "rate limited"}), "someone").unwrap_err();
+        assert!(error.is::<UnexpectedUpstreamFormat>());
+        assert!(error.to_string().contains("rate limited"));
+    }
+
+    #[test]
+    fn search_results_without_an_id_are_an_error() {
+        let response = json!([{"nickname": "someone"}, {"id": "not a number"}]);
+        assert!(parse_search_response(&response, "someone").is_err());
+    }
+
+    #[test]
+    fn three_player_matches_with_four_seats_are_skipped() {
+        let four_seats = record(
+            24,
+            &[
+                (1, 40_000, 60),
+                (2, 30_000, 10),
+                (3, 20_000, -20),
+                (4, 15_000, -50),
+            ],
+        );
+        assert!(parse_single_match(&four_seats, 4, false).is_err());
+
+        let mut report = ParseReport::default();
+        let records = [
+            four_seats,
+            record(24, &[(1, 40_000, 60), (4, 30_000, 10), (3, 35_000, -70)]),
+        ];
+        let matches = parse_match_data(&records, 4, false, &mut report);
+        assert_eq!(matches.len(), 1);
+        assert!(matches.iter().all(|game| game.player_rank <= 3));
+        assert_eq!(report.skipped_records, 1);
+    }
+
+    #[test]
+    fn four_player_matches_with_three_seats_are_skipped() {
+        let three_seats = record(12, &[(1, 40_000, 60), (2, 30_000, 10), (3, 35_000, -70)]);
+        assert!(parse_single_match(&three_seats, 1, false).is_err());
+    }
+
+    #[test]
+    fn matches_without_the_target_player_are_skipped() {
+        let mut records = fixture_records("records_4p.json");
+        records[0]["players"][0]["accountId"] = json!(9999);
+
+        let mut report = ParseReport::default();
+        let matches = parse_match_data(&records, 1001, false, &mut report);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].start_time.timestamp(), 1_697_100_000);
+        assert_eq!(report.skipped_records, 1);
+    }
+
+    #[test]
+    fn matches_listing_an_account_twice_are_skipped() {
+        let duplicated = record(
+            12,
+            &[
+                (1, 40_000, 60),
+                (2, 30_000, 10),
+                (1, 20_000, -20),
+                (4, 10_000, -50),
+            ],
+        );
+        let error = parse_single_match(&duplicated, 1, false).unwrap_err();
+        assert!(error.to_string().contains("more than once"));
+
+        // Also when the repeated account isn't the target
+        assert!(parse_single_match(&duplicated, 4, false).is_err());
+    }
+
+    #[test]
+    fn a_short_page_is_the_only_page() {
+        let records = fixture_records("records_4p.json");
+        assert_eq!(next_page(&records, 500, 1_697_300_000), NextPage::Done);
+
+        let mut report = ParseReport::default();
+        let matches = parse_match_data(&records, 1001, false, &mut report);
+        assert_eq!(matches.len() + report.skipped_records, records.len());
+    }
+
+    #[test]
+    fn paging_continues_past_unparseable_records() {
+        // Only the newest record parses; the cursor still moves past the oldest one
+        let mut records = fixture_records("records_4p.json");
+        records[1]["modeId"] = json!(40);
+        let mut report = ParseReport::default();
+        assert_eq!(
+            parse_match_data(&records, 1001, false, &mut report).len(),
+            1
+        );
+        assert_eq!(
+            next_page(&records, records.len(), 1_697_300_000),
+            NextPage::Before(1_697_099_999)
+        );
+    }
+
+    #[test]
+    fn a_full_page_without_start_times_is_stuck() {
+        let records = vec![json!({"players": []}); 3];
+        assert_eq!(next_page(&records, 3, 1_697_300_000), NextPage::Stuck);
+
+        // Nor may the cursor fail to move back in time
+        let records = fixture_records("records_4p.json");
+        assert_eq!(
+            next_page(&records, records.len(), 1_697_099_999),
+            NextPage::Stuck
+        );
+    }
+
+    #[test]
+    fn one_record_without_the_expected_keys_is_not_a_format_change() {
+        let mut records = fixture_records("records_4p.json");
+        records.insert(0, json!({"_id": "odd"}));
+        assert!(probe_record_schema(&Value::Array(records)).is_ok());
+
+        let records = vec![json!({"_id": "odd"}); 3];
+        let error = probe_record_schema(&Value::Array(records)).unwrap_err();
+        assert!(error.is::<UnexpectedUpstreamFormat>());
+    }
+
+    #[test]
+    fn signed_pt_is_grouped_like_other_totals() {
+        let signed = |value: i64, separator: Option<char>| {
+            filters::signed_grouped(value, askama::NO_VALUES, &separator).unwrap()
+        };
+        assert_eq!(signed(12_345, Some(',')), "+12,345");
+        assert_eq!(signed(-1_234_567, Some('.')), "-1.234.567");
+        assert_eq!(signed(0, Some(',')), "+0");
+        assert_eq!(signed(12_345, None), "+12345");
+    }
+
+    #[test]
+    fn four_player_ties_break_by_final_score_then_account_id() {
+        // 2 and 3 tie on pt change, 3 wins on final score; 4 and 5 tie on both
+        let players = [
+            (4, 10_000, -30),
+            (2, 27_000, 15),
+            (5, 10_000, -30),
+            (3, 28_000, 15),
+        ];
+        let forward = record(12, &players);
+        assert_eq!(rank_of(&forward, 3), 1);
+        assert_eq!(rank_of(&forward, 2), 2);
+        assert_eq!(rank_of(&forward, 4), 3);
+        assert_eq!(rank_of(&forward, 5), 4);
+
+        let mut reversed_players = players;
+        reversed_players.reverse();
+        let reversed = record(12, &reversed_players);
+        for player_id in [2, 3, 4, 5] {
+            assert_eq!(rank_of(&reversed, player_id), rank_of(&forward, player_id));
+        }
+    }
+
+    #[test]
+    fn three_player_ties_break_by_account_id_regardless_of_order() {
+        let players = [(9, 35_000, 0), (7, 35_000, 0), (8, 35_000, 0)];
+        let forward = record(24, &players);
+        assert_eq!(rank_of(&forward, 7), 1);
+        assert_eq!(rank_of(&forward, 8), 2);
+        assert_eq!(rank_of(&forward, 9), 3);
+
+        let mut reversed_players = players;
+        reversed_players.reverse();
+        let reversed = record(24, &reversed_players);
+        for player_id in [7, 8, 9] {
+            assert_eq!(rank_of(&reversed, player_id), rank_of(&forward, player_id));
+        }
+    }
+
+    #[test]
+    fn upstream_placement_wins_over_recomputed_ties() {
+        let mut tied = record(24, &[(7, 35_000, 0), (8, 35_000, 0), (9, 35_000, 0)]);
+        for (player, placement) in tied["players"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .zip([3, 1, 2])
+        {
+            player["placement"] = json!(placement);
+        }
+        let game = parse_single_match(&tied, 7, false).unwrap();
+        assert_eq!(game.player_rank, 3);
+        assert_eq!(game.placement_source, PlacementSource::Upstream);
+    }
 }